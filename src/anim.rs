@@ -0,0 +1,355 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How a window appears when mapped, and disappears when unmapped
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    None,
+    /// Grows from slightly smaller than the window
+    Scale,
+    /// Comes down from above its position
+    Slide,
+    /// Grows from the center of the window
+    Zoom,
+}
+
+/// Easing curve, written as `"linear"`, `"cubic-bezier(x1, y1, x2, y2)"`
+/// or `"spring(stiffness, damping)"`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Curve {
+    Linear,
+    /// Same as the CSS function, control points go from (0, 0) to (1, 1)
+    CubicBezier([f32; 4]),
+    /// Damped spring of unit mass, settling at 1
+    Spring {
+        stiffness: f32,
+        damping: f32,
+    },
+}
+
+impl Curve {
+    /// Eased progress at `t`, between 0 and 1. Springs may overshoot.
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Curve::Linear => t,
+            Curve::CubicBezier([x1, y1, x2, y2]) => {
+                let s = solve_bezier(x1, x2, t);
+                bezier(y1, y2, s)
+            }
+            Curve::Spring { stiffness, damping } => {
+                spring(stiffness, damping, t)
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for Curve {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Curve, String> {
+        let invalid = || {
+            format!(
+                "invalid curve `{}`, expected \"linear\", \
+                 \"cubic-bezier(x1, y1, x2, y2)\" or \
+                 \"spring(stiffness, damping)\"",
+                value
+            )
+        };
+        if value.trim() == "linear" {
+            return Ok(Curve::Linear);
+        }
+        let (name, args) = value
+            .trim()
+            .strip_suffix(')')
+            .and_then(|v| v.split_once('('))
+            .ok_or_else(invalid)?;
+        let args = args
+            .split(',')
+            .map(|arg| arg.trim().parse::<f32>())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|_| invalid())?;
+        match (name.trim(), args.as_slice()) {
+            ("cubic-bezier", &[x1, y1, x2, y2]) => {
+                if !(0.0..=1.0).contains(&x1) || !(0.0..=1.0).contains(&x2) {
+                    return Err(format!(
+                        "invalid curve `{}`, x1 and x2 must be between 0 and 1",
+                        value
+                    ));
+                }
+                Ok(Curve::CubicBezier([x1, y1, x2, y2]))
+            }
+            ("spring", &[stiffness, damping]) => {
+                if stiffness <= 0.0 || damping <= 0.0 {
+                    return Err(format!(
+                        "invalid curve `{}`, stiffness and damping must be \
+                         positive",
+                        value
+                    ));
+                }
+                Ok(Curve::Spring { stiffness, damping })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Curve::Linear => write!(f, "linear"),
+            Curve::CubicBezier([x1, y1, x2, y2]) => {
+                write!(f, "cubic-bezier({}, {}, {}, {})", x1, y1, x2, y2)
+            }
+            Curve::Spring { stiffness, damping } => {
+                write!(f, "spring({}, {})", stiffness, damping)
+            }
+        }
+    }
+}
+
+impl From<Curve> for String {
+    fn from(curve: Curve) -> String {
+        curve.to_string()
+    }
+}
+
+/// One coordinate of a cubic bezier going from 0 to 1, at `s`
+fn bezier(p1: f32, p2: f32, s: f32) -> f32 {
+    let inv = 1.0 - s;
+    3.0 * inv * inv * s * p1 + 3.0 * inv * s * s * p2 + s * s * s
+}
+
+/// Finds `s` where the x coordinate of the curve is `x`, by bisection
+/// since x is monotonic for control points between 0 and 1
+fn solve_bezier(x1: f32, x2: f32, x: f32) -> f32 {
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..24 {
+        let mid = (low + high) / 2.0;
+        if bezier(x1, x2, mid) < x {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// Position of a spring released at 0, resting at 1. The time it takes
+/// to settle is stretched to `t` going from 0 to 1, so the animation
+/// duration is kept whatever the spring.
+fn spring(stiffness: f32, damping: f32, t: f32) -> f32 {
+    let omega = stiffness.sqrt();
+    let zeta = damping / (2.0 * omega);
+    // Time for the oscillations to decay to 0.1%
+    let settle = 6.9 / (zeta.min(1.0) * omega);
+    let time = t * settle;
+    if zeta < 1.0 {
+        let omega_d = omega * (1.0 - zeta * zeta).sqrt();
+        let decay = (-zeta * omega * time).exp();
+        1.0 - decay
+            * ((omega_d * time).cos()
+                + zeta * omega / omega_d * (omega_d * time).sin())
+    } else {
+        // Critically damped, overdamped springs are close enough to it
+        1.0 - (-omega * time).exp() * (1.0 + omega * time)
+    }
+}
+
+/// Open or close animation of a window
+#[derive(Debug, Clone, Copy)]
+pub struct Animation {
+    kind: Kind,
+    curve: Curve,
+    start: Instant,
+    duration: Duration,
+    /// Plays backwards, from the window to nothing
+    closing: bool,
+    /// Eased progress as of the last step, 1 being the window at rest
+    progress: f32,
+}
+
+impl Animation {
+    pub fn new(
+        kind: Kind,
+        curve: Curve,
+        duration: Duration,
+        closing: bool,
+    ) -> Animation {
+        Animation {
+            kind,
+            curve,
+            start: Instant::now(),
+            duration,
+            closing,
+            progress: if closing { 1.0 } else { 0.0 },
+        }
+    }
+
+    /// Advances the animation to `now`, returns false once it is over
+    pub fn step(&mut self, now: Instant) -> bool {
+        let t = now.duration_since(self.start).as_secs_f32()
+            / self.duration.as_secs_f32();
+        if t >= 1.0 {
+            return false;
+        }
+        let eased = self.curve.apply(t);
+        self.progress = if self.closing { 1.0 - eased } else { eased };
+        true
+    }
+
+    /// Transform of a window `height` pixels high at the current step
+    pub fn transform(&self, height: f32) -> Transform {
+        let p = self.progress;
+        match self.kind {
+            Kind::None => Transform::IDENTITY,
+            Kind::Scale => {
+                Transform { scale: 0.8 + 0.2 * p, offset: [0.0, 0.0] }
+            }
+            Kind::Zoom => Transform { scale: p.max(0.0), offset: [0.0, 0.0] },
+            Kind::Slide => {
+                Transform { scale: 1.0, offset: [0.0, -(1.0 - p) * height] }
+            }
+        }
+    }
+}
+
+/// Scale around the center of a window, followed by a translation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub scale: f32,
+    pub offset: [f32; 2],
+}
+
+impl Transform {
+    pub const IDENTITY: Transform =
+        Transform { scale: 1.0, offset: [0.0, 0.0] };
+}
+
+/// Paces the frames drawn for animations, which have to be drawn even
+/// when no events come in
+pub struct Clock {
+    last_frame: Instant,
+    interval: Duration,
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock { last_frame: Instant::now(), interval: Duration::ZERO }
+    }
+
+    /// Whether the next frame is due, `interval` after the last one.
+    /// The interval applies right away, even if the previous one was
+    /// longer.
+    pub fn tick(&mut self, interval: Duration) -> bool {
+        self.interval = interval;
+        let now = Instant::now();
+        if now < self.last_frame + interval {
+            return false;
+        }
+        self.last_frame = now;
+        true
+    }
+
+    /// Time left until the next frame
+    pub fn timeout(&self) -> Duration {
+        (self.last_frame + self.interval)
+            .saturating_duration_since(Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve(value: &str) -> Result<Curve, String> {
+        Curve::try_from(value.to_string())
+    }
+
+    #[test]
+    fn parses_curves() {
+        assert_eq!(curve(" linear "), Ok(Curve::Linear));
+        assert_eq!(
+            curve("cubic-bezier(0.25, 0.1, 0.25, 1)"),
+            Ok(Curve::CubicBezier([0.25, 0.1, 0.25, 1.0]))
+        );
+        assert_eq!(
+            curve("spring(170,26)"),
+            Ok(Curve::Spring { stiffness: 170.0, damping: 26.0 })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_curves() {
+        assert!(curve("ease").is_err());
+        assert!(curve("cubic-bezier(0, 0, 1)").is_err());
+        assert!(curve("cubic-bezier(0, a, 1, 1)").is_err());
+        assert!(curve("spring(1, 2").is_err());
+        assert_eq!(
+            curve("cubic-bezier(1.5, 0, 1, 1)").unwrap_err(),
+            "invalid curve `cubic-bezier(1.5, 0, 1, 1)`, x1 and x2 must be \
+             between 0 and 1"
+        );
+        assert!(curve("spring(0, 10)").is_err());
+    }
+
+    #[test]
+    fn curves_round_trip() {
+        for value in
+            ["linear", "cubic-bezier(0.4, 0, 0.2, 1)", "spring(200, 20)"]
+        {
+            let parsed = curve(value).unwrap();
+            assert_eq!(curve(&String::from(parsed)), Ok(parsed));
+        }
+    }
+
+    #[test]
+    fn bezier_goes_from_0_to_1() {
+        let ease = Curve::CubicBezier([0.25, 0.1, 0.25, 1.0]);
+        assert!(ease.apply(0.0).abs() < 1e-4);
+        assert!((ease.apply(1.0) - 1.0).abs() < 1e-4);
+        // Eases in fast, ahead of linear halfway through
+        assert!(ease.apply(0.5) > 0.5);
+        let linear = Curve::CubicBezier([0.0, 0.0, 1.0, 1.0]);
+        for t in [0.1, 0.3, 0.7] {
+            assert!((linear.apply(t) - t).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn solves_bezier_x() {
+        for x in [0.0, 0.2, 0.5, 0.9, 1.0] {
+            let s = solve_bezier(0.42, 0.58, x);
+            assert!((bezier(0.42, 0.58, s) - x).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn springs_settle_at_1() {
+        let bouncy = Curve::Spring { stiffness: 200.0, damping: 10.0 };
+        let stiff = Curve::Spring { stiffness: 100.0, damping: 40.0 };
+        for spring in [bouncy, stiff] {
+            assert!(spring.apply(0.0).abs() < 1e-4);
+            assert!((spring.apply(1.0) - 1.0).abs() < 0.01);
+        }
+        // Underdamped springs overshoot, critically damped ones don't
+        let values =
+            |c: Curve| (0..100).map(move |i| c.apply(i as f32 / 100.0));
+        assert!(values(bouncy).any(|v| v > 1.05));
+        assert!(values(stiff).all(|v| v <= 1.0 + 1e-4));
+    }
+
+    #[test]
+    fn applies_shorter_intervals_right_away() {
+        let hour = Duration::from_secs(3600);
+        let mut clock = Clock::new();
+        assert!(clock.tick(Duration::ZERO));
+        assert!(!clock.tick(hour));
+        assert!(clock.timeout() > hour / 2);
+        // Animations starting during a long night light step
+        assert!(clock.tick(Duration::ZERO));
+        assert_eq!(clock.timeout(), Duration::ZERO);
+    }
+}
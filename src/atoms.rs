@@ -0,0 +1,49 @@
+/// Atoms used by the compositor, interned once at startup.
+pub struct Atoms {
+    /// Set on the root window to request a dump of the current frame,
+    /// the value is the path of the output file
+    pub rcomp_screenshot: xcb::Atom,
+    /// Set on the root window or on client windows to override the
+    /// colour filters of the config or rules
+    pub rcomp_color_filters: xcb::Atom,
+    /// Set by the window manager on client windows
+    pub wm_state: xcb::Atom,
+    pub wm_window_role: xcb::Atom,
+    pub net_wm_name: xcb::Atom,
+    pub net_wm_window_type: xcb::Atom,
+    pub net_wm_window_opacity: xcb::Atom,
+    pub net_wm_state: xcb::Atom,
+    pub net_wm_state_fullscreen: xcb::Atom,
+    /// Set by the window manager on the root window
+    pub net_active_window: xcb::Atom,
+    /// Set by clients on the regions they want blurred
+    pub kde_net_wm_blur_behind_region: xcb::Atom,
+}
+
+impl Atoms {
+    pub fn intern(conn: &xcb::Connection) -> Result<Atoms, xcb::GenericError> {
+        Ok(Atoms {
+            rcomp_screenshot: intern(conn, "_RCOMP_SCREENSHOT")?,
+            rcomp_color_filters: intern(conn, "_RCOMP_COLOR_FILTERS")?,
+            wm_state: intern(conn, "WM_STATE")?,
+            wm_window_role: intern(conn, "WM_WINDOW_ROLE")?,
+            net_wm_name: intern(conn, "_NET_WM_NAME")?,
+            net_wm_window_type: intern(conn, "_NET_WM_WINDOW_TYPE")?,
+            net_wm_window_opacity: intern(conn, "_NET_WM_WINDOW_OPACITY")?,
+            net_wm_state: intern(conn, "_NET_WM_STATE")?,
+            net_wm_state_fullscreen: intern(conn, "_NET_WM_STATE_FULLSCREEN")?,
+            net_active_window: intern(conn, "_NET_ACTIVE_WINDOW")?,
+            kde_net_wm_blur_behind_region: intern(
+                conn,
+                "_KDE_NET_WM_BLUR_BEHIND_REGION",
+            )?,
+        })
+    }
+}
+
+fn intern(
+    conn: &xcb::Connection,
+    name: &str,
+) -> Result<xcb::Atom, xcb::GenericError> {
+    Ok(xcb::intern_atom(conn, false, name).get_reply()?.atom())
+}
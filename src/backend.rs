@@ -1 +1,62 @@
+pub mod headless;
 pub mod opengl;
+pub mod xrender;
+
+use crate::config::Config;
+use crate::filter::ColorFilter;
+use crate::region::Region;
+use crate::window::Window;
+use std::io;
+use std::path::Path;
+
+/// Interface every rendering backend has to provide.
+///
+/// Backends keep their own per-window data in `Context`, which gets
+/// stored inside each tracked `Window`.
+pub trait Backend {
+    /// Per-window backend data
+    type Context: Default;
+
+    /// Sets up the backend data for a newly mapped window
+    fn init_window(&self, win: &mut Window<Self::Context>);
+    /// Recreates the window pixmap, called when it gets invalidated
+    /// (map, resize, etc.)
+    fn update_pixmap(&self, win: &mut Window<Self::Context>);
+    /// Updates the on-screen position of the window
+    fn update_pos(&self, win: &Window<Self::Context>);
+    /// Refreshes the window contents from its pixmap
+    fn update_texture(&self, win: &mut Window<Self::Context>);
+    /// Rebuilds the shadow and rounded corners of the window, called when
+    /// its size, shape or effect settings change
+    fn update_masks(&self, win: &mut Window<Self::Context>);
+    /// Limits the frame about to be drawn to `region`, the rest of the
+    /// screen keeping what the last frame showed there. Frames are drawn
+    /// whole otherwise, as is done by backends that can't keep them.
+    ///
+    /// Only the OpenGL backend draws partial frames. The xrender one
+    /// composites every window on each frame, and the headless one
+    /// draws whole frames, which keeps every saved frame complete.
+    fn set_damage(&self, _region: Region, _windows: &[Window<Self::Context>]) {
+    }
+    /// Draws the window on top of everything drawn so far
+    fn draw_window(&self, win: &Window<Self::Context>);
+    /// Presents the drawn frame and starts a new one
+    fn render(&self);
+    /// Applies the settings of a reloaded config.
+    /// Windows have their textures and masks updated right after.
+    fn reconfigure(&mut self, config: &Config);
+    /// Follows a change of the RandR outputs, picking the LUTs of
+    /// `config` for the new ones
+    fn update_outputs(&mut self, _config: &Config) {}
+    /// Sets the colour filters applied to the whole screen, restarting
+    /// the frame being drawn. Called on startup and after `reconfigure`.
+    fn set_color_filters(&mut self, filters: &[ColorFilter]) {
+        if !filters.is_empty() {
+            warn!("Colour filters are not supported by this backend");
+        }
+    }
+    /// Writes the last presented frame to `path`
+    fn save_frame(&self, _path: &Path) -> io::Result<()> {
+        Err(io::Error::other("saving frames is not supported by this backend"))
+    }
+}
@@ -0,0 +1,547 @@
+use super::Backend;
+use crate::config::{Config, CornerConfig, NightLightConfig, ShadowConfig};
+use crate::corners::Corners;
+use crate::filter::{ColorFilter, ColorMatrix};
+use crate::lut::{self, Correction};
+use crate::night;
+use crate::shadow::{self, Mask};
+use crate::state::State;
+use crate::window::Window;
+use std::cell::{Cell, RefCell};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Backend compositing on the CPU into an offscreen buffer, the overlay
+/// window is never drawn to.
+///
+/// Meant for running under Xvfb in CI: every presented frame can be
+/// written to `output_dir` as a PPM image, or single frames can be
+/// requested through `save_frame`.
+pub struct Headless<'a> {
+    pub conn: &'a xcb::Connection,
+    /// Directory every presented frame gets written to, if any
+    pub output_dir: Option<PathBuf>,
+    /// Darkening of unfocused windows, from the config
+    inactive_dim: f32,
+    shadow: ShadowConfig,
+    corners: CornerConfig,
+    night_light: NightLightConfig,
+    /// Colour filters of the whole screen
+    color_matrix: ColorMatrix,
+    /// LUTs applied to presented frames
+    corrections: Vec<Correction>,
+    /// Root window, outputs are looked up on it
+    root: xcb::Window,
+    blur_strength: u8,
+    width: usize,
+    height: usize,
+    /// Root window contents
+    background: Vec<u32>,
+    /// Frame currently being drawn
+    frame: RefCell<Vec<u32>>,
+    /// Last presented frame
+    presented: RefCell<Vec<u32>>,
+    frame_count: Cell<u64>,
+    /// Server image byte order
+    msb_first: bool,
+}
+
+impl<'a> Headless<'a> {
+    /// Box blurs the frame behind `win`
+    fn blur_behind(&self, win: &Window<BackendContext>) {
+        let mut frame = self.frame.borrow_mut();
+        let radius = self.blur_strength as usize;
+        // Pixels around the window are part of the blur too
+        let pad = radius as isize * 3;
+        let win_rect = win.draw_rect(
+            0.0,
+            0.0,
+            win.outer_width() as f32,
+            win.outer_height() as f32,
+        );
+        let [left, top, right, bottom] = self.clip(win_rect);
+        let x0 = (left as isize - pad).max(0) as usize;
+        let y0 = (top as isize - pad).max(0) as usize;
+        let x1 = (right + pad as usize).min(self.width);
+        let y1 = (bottom + pad as usize).min(self.height);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        let (width, height) = (x1 - x0, y1 - y0);
+
+        // Blurred one channel at a time
+        let mut planes = [0, 8, 16, 24].map(|shift| {
+            let mut plane = Vec::with_capacity(width * height);
+            for y in y0..y1 {
+                let row = &frame[y * self.width + x0..y * self.width + x1];
+                plane.extend(row.iter().map(|p| (p >> shift) as u8));
+            }
+            plane
+        });
+        for plane in planes.iter_mut() {
+            for _ in 0..3 {
+                shadow::blur(plane, width, height, radius, 1, width);
+                shadow::blur(plane, height, width, radius, width, 1);
+            }
+        }
+
+        for rect in win.blur_rects() {
+            let [rx0, ry0, rx1, ry1] = self.clip(win.draw_rect(
+                rect.x() as f32,
+                rect.y() as f32,
+                rect.width() as f32,
+                rect.height() as f32,
+            ));
+            let (rx0, rx1) = (rx0.clamp(x0, x1), rx1.clamp(x0, x1));
+            let (ry0, ry1) = (ry0.clamp(y0, y1), ry1.clamp(y0, y1));
+            for y in ry0..ry1 {
+                for x in rx0..rx1 {
+                    let i = (y - y0) * width + (x - x0);
+                    let blurred = planes[0][i] as u32
+                        | (planes[1][i] as u32) << 8
+                        | (planes[2][i] as u32) << 16
+                        | (planes[3][i] as u32) << 24;
+                    let dst = &mut frame[y * self.width + x];
+                    *dst = match &win.context.corners {
+                        // Rounded corners only blur what they cover
+                        Some(corners) => {
+                            let zoom = win.transform().scale;
+                            let cx =
+                                source(x, win_rect[0], zoom, corners.width);
+                            let cy =
+                                source(y, win_rect[1], zoom, corners.height);
+                            let inside =
+                                corners.inside[cy * corners.width + cx] as u32;
+                            mix(*dst, blurred, inside)
+                        }
+                        None => blurred,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Looks the colours of the frame up in the LUT of `correction`,
+    /// within its rects
+    fn correct(&self, correction: &Correction) {
+        let mut frame = self.frame.borrow_mut();
+        for &[x, y, width, height] in &correction.rects {
+            let [x0, y0, x1, y1] =
+                self.clip([x as f32, y as f32, width as f32, height as f32]);
+            for y in y0..y1 {
+                for pixel in
+                    &mut frame[y * self.width + x0..y * self.width + x1]
+                {
+                    let rgb = [16, 8, 0].map(|shift| {
+                        ((*pixel >> shift) & 0xff) as f32 / 255.0
+                    });
+                    let [r, g, b] = correction
+                        .lut
+                        .sample(rgb)
+                        .map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u32);
+                    *pixel = (*pixel & 0xff00_0000) | r << 16 | g << 8 | b;
+                }
+            }
+        }
+    }
+
+    pub fn init(
+        state: &'a State,
+        config: &Config,
+    ) -> Result<Headless<'a>, &'static str> {
+        let output_dir = config.headless.output_dir.clone();
+        if let Some(dir) = &output_dir {
+            fs::create_dir_all(dir)
+                .map_err(|_| "unable to create output directory")?;
+        }
+        let msb_first = state.conn.get_setup().image_byte_order()
+            == xcb::IMAGE_ORDER_MSB_FIRST as u8;
+        let width = state.root.width as usize;
+        let height = state.root.height as usize;
+
+        let background = if state.root.pixmap != xcb::NONE {
+            fetch_image(
+                &state.conn,
+                state.root.pixmap,
+                state.root.width,
+                state.root.height,
+                msb_first,
+            )
+        } else {
+            None
+        }
+        .unwrap_or_else(|| vec![0xff00_0000; width * height]);
+
+        Ok(Headless {
+            conn: &state.conn,
+            output_dir,
+            inactive_dim: config.inactive_dim,
+            shadow: config.shadow.clone(),
+            corners: config.corners.clone(),
+            night_light: config.night_light.clone(),
+            color_matrix: ColorMatrix::IDENTITY,
+            corrections: lut::load(
+                &state.conn,
+                state.root.id,
+                &config.lut,
+                [state.root.width, state.root.height],
+            ),
+            root: state.root.id,
+            blur_strength: config.blur.strength,
+            width,
+            height,
+            frame: RefCell::new(background.clone()),
+            presented: RefCell::new(background.clone()),
+            background,
+            frame_count: Cell::new(0),
+            msb_first,
+        })
+    }
+}
+
+impl<'a> Headless<'a> {
+    /// Pixels of the frame covered by a rectangle from `Window::draw_rect`,
+    /// as [x0, y0, x1, y1]
+    fn clip(&self, [x, y, width, height]: [f32; 4]) -> [usize; 4] {
+        let clamp = |v: f32, max: usize| v.round().clamp(0.0, max as f32);
+        [
+            clamp(x, self.width) as usize,
+            clamp(y, self.height) as usize,
+            clamp(x + width, self.width) as usize,
+            clamp(y + height, self.height) as usize,
+        ]
+    }
+
+    fn draw_shadow(&self, win: &Window<BackendContext>, mask: &Mask) {
+        let mut frame = self.frame.borrow_mut();
+        let alpha = self.shadow.opacity * win.draw_opacity();
+        let [r, g, b] =
+            self.shadow.color.to_f32().map(|c| (c * alpha * 255.0) as u32);
+        let color = ((alpha * 255.0) as u32) << 24 | r << 16 | g << 8 | b;
+
+        let radius = self.shadow.radius as f32;
+        let [dx, dy] = self.shadow.offset;
+        let rect = win.draw_rect(
+            dx as f32 - radius,
+            dy as f32 - radius,
+            win.outer_width() as f32 + radius * 2.0,
+            win.outer_height() as f32 + radius * 2.0,
+        );
+        let [x0, y0, x1, y1] = self.clip(rect);
+        // Masks built before a resize are stretched until it settles
+        let zoom_x = rect[2] / mask.width as f32;
+        let zoom_y = rect[3] / mask.height as f32;
+        for y in y0..y1 {
+            let src_y = source(y, rect[1], zoom_y, mask.height);
+            for x in x0..x1 {
+                let src_x = source(x, rect[0], zoom_x, mask.width);
+                let value = mask.data[src_y * mask.width + src_x];
+                let dst = &mut frame[y * self.width + x];
+                *dst = blend_over(scale(color, value as u32), *dst);
+            }
+        }
+    }
+}
+
+impl<'a> Backend for Headless<'a> {
+    type Context = BackendContext;
+
+    fn init_window(&self, _win: &mut Window<BackendContext>) {
+        // Contents are fetched on `update_texture`
+    }
+
+    fn update_pixmap(&self, win: &mut Window<BackendContext>) {
+        // The window can be destroyed before the event gets handled, it is
+        // then left out until it is gone
+        if let Err(e) = win.update_pixmap(self.conn) {
+            warn!("Unable to get the pixmap of window {}: {}", win.id, e);
+            let ctx = &mut win.context;
+            ctx.image = Vec::new();
+            (ctx.width, ctx.height) = (0, 0);
+        }
+    }
+
+    fn update_pos(&self, _win: &Window<BackendContext>) {
+        // Position is only used while drawing
+    }
+
+    fn update_texture(&self, win: &mut Window<BackendContext>) {
+        let width = win.width + win.border_width * 2;
+        let height = win.height + win.border_width * 2;
+        match fetch_image(self.conn, win.pixmap, width, height, self.msb_first)
+        {
+            Some(mut image) => {
+                // Only the alpha visuals have meaningful alpha bits
+                if !win.alpha {
+                    image.iter_mut().for_each(|p| *p |= 0xff00_0000);
+                }
+                win.context.image = image;
+                win.context.width = width as usize;
+                win.context.height = height as usize;
+            }
+            None => warn!("Unable to get image of window: {}", win.id),
+        }
+    }
+
+    fn update_masks(&self, win: &mut Window<BackendContext>) {
+        let corner_radius = win.corner_radius(self.corners.radius);
+        win.context.shadow = if win.has_shadow(self.shadow.enabled) {
+            Some(Mask::build(win, self.shadow.radius, corner_radius))
+        } else {
+            None
+        };
+        let border_width = self.corners.border_width;
+        win.context.corners =
+            if win.rounded() && (corner_radius > 0 || border_width > 0) {
+                Some(Corners::build(
+                    win.outer_width() as usize,
+                    win.outer_height() as usize,
+                    corner_radius as f32,
+                    border_width as f32,
+                ))
+            } else {
+                None
+            };
+    }
+
+    fn draw_window(&self, win: &Window<BackendContext>) {
+        let ctx = &win.context;
+        let zoom = win.transform().scale;
+        // Zooming windows start from nothing
+        if zoom <= 0.0 {
+            return;
+        }
+        if let Some(mask) = &ctx.shadow {
+            self.draw_shadow(win, mask);
+        }
+        if win.has_blur() {
+            self.blur_behind(win);
+        }
+        let mut frame = self.frame.borrow_mut();
+
+        // Clip the window to the screen
+        let rect =
+            win.draw_rect(0.0, 0.0, ctx.width as f32, ctx.height as f32);
+        let [x0, y0, x1, y1] = self.clip(rect);
+
+        let opacity = (win.draw_opacity() * 255.0).round() as u32;
+        let brightness = if win.dimmed() {
+            ((1.0 - self.inactive_dim) * 255.0).round() as u32
+        } else {
+            255
+        };
+        // Masks built before a resize are stretched until it settles
+        let corners = ctx.corners.as_ref().map(|corners| {
+            let zoom_x = rect[2] / corners.width as f32;
+            let zoom_y = rect[3] / corners.height as f32;
+            (corners, zoom_x, zoom_y)
+        });
+        let [r, g, b] = self
+            .corners
+            .border_color
+            .to_f32()
+            .map(|c| (c * 255.0).round() as u32);
+        let border_color = 0xff00_0000 | r << 16 | g << 8 | b;
+        let color_matrix = win.color_matrix();
+        for y in y0..y1 {
+            let src_row = source(y, rect[1], zoom, ctx.height) * ctx.width;
+            for x in x0..x1 {
+                let i = src_row + source(x, rect[0], zoom, ctx.width);
+                let dst = &mut frame[y * self.width + x];
+                let mut src = filtered(ctx.image[i], &color_matrix);
+                src = scale_rgb(src, brightness);
+                if let Some((corners, zoom_x, zoom_y)) = corners {
+                    let j = source(y, rect[1], zoom_y, corners.height)
+                        * corners.width
+                        + source(x, rect[0], zoom_x, corners.width);
+                    if !corners.border.is_empty() {
+                        src = mix(src, border_color, corners.border[j] as u32);
+                    }
+                    src = scale(src, corners.inside[j] as u32);
+                }
+                *dst = blend_over(scale(src, opacity), *dst);
+            }
+        }
+    }
+
+    fn render(&self) {
+        let count = self.frame_count.get() + 1;
+        self.frame_count.set(count);
+        if !self.color_matrix.is_identity() {
+            for pixel in self.frame.borrow_mut().iter_mut() {
+                *pixel = filtered(*pixel, &self.color_matrix);
+            }
+        }
+        if let Some(white_point) =
+            night::current(&self.night_light, night::local_time())
+        {
+            for pixel in self.frame.borrow_mut().iter_mut() {
+                *pixel = tint(*pixel, white_point);
+            }
+        }
+        for correction in &self.corrections {
+            self.correct(correction);
+        }
+        self.presented.swap(&self.frame);
+        if let Some(dir) = &self.output_dir {
+            let path = dir.join(format!("frame-{:06}.ppm", count));
+            if let Err(e) = self.save_frame(&path) {
+                error!("Unable to write {}: {}", path.display(), e);
+            }
+        }
+        // Start the next frame
+        self.frame.borrow_mut().copy_from_slice(&self.background);
+    }
+
+    fn reconfigure(&mut self, config: &Config) {
+        self.inactive_dim = config.inactive_dim;
+        self.shadow = config.shadow.clone();
+        self.corners = config.corners.clone();
+        self.night_light = config.night_light.clone();
+        self.update_outputs(config);
+        self.blur_strength = config.blur.strength;
+        if let Some(dir) = &config.headless.output_dir {
+            if let Err(e) = fs::create_dir_all(dir) {
+                warn!("Unable to create {}: {}", dir.display(), e);
+                return;
+            }
+        }
+        self.output_dir = config.headless.output_dir.clone();
+    }
+
+    fn update_outputs(&mut self, config: &Config) {
+        let size = [self.width as u16, self.height as u16];
+        self.corrections = lut::load(self.conn, self.root, &config.lut, size);
+    }
+
+    fn set_color_filters(&mut self, filters: &[ColorFilter]) {
+        self.color_matrix = ColorMatrix::new(filters);
+    }
+
+    fn save_frame(&self, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write!(file, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for pixel in self.presented.borrow().iter() {
+            file.write_all(&[
+                (pixel >> 16) as u8,
+                (pixel >> 8) as u8,
+                *pixel as u8,
+            ])?;
+        }
+        file.flush()
+    }
+}
+
+#[derive(Default)]
+pub struct BackendContext {
+    /// Window contents as premultiplied 0xAARRGGBB pixels
+    pub image: Vec<u32>,
+    pub width: usize,
+    pub height: usize,
+    pub shadow: Option<Mask>,
+    /// Coverage of rounded windows
+    pub corners: Option<Corners>,
+}
+
+/// Reads the contents of a 24/32 bit drawable as 0xAARRGGBB pixels
+fn fetch_image(
+    conn: &xcb::Connection,
+    drawable: xcb::Drawable,
+    width: u16,
+    height: u16,
+    msb_first: bool,
+) -> Option<Vec<u32>> {
+    let reply = xcb::get_image(
+        conn,
+        xcb::IMAGE_FORMAT_Z_PIXMAP as u8,
+        drawable,
+        0,
+        0,
+        width,
+        height,
+        !0,
+    )
+    .get_reply()
+    .ok()?;
+    let data = reply.data();
+    if reply.depth() < 24 || data.len() != width as usize * height as usize * 4
+    {
+        return None;
+    }
+    Some(
+        data.chunks_exact(4)
+            .map(|p| {
+                let bytes = [p[0], p[1], p[2], p[3]];
+                if msb_first {
+                    u32::from_be_bytes(bytes)
+                } else {
+                    u32::from_le_bytes(bytes)
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Nearest source pixel of the frame pixel `pos`, for an image of `len`
+/// pixels drawn from `start` and scaled by `zoom`
+fn source(pos: usize, start: f32, zoom: f32, len: usize) -> usize {
+    let src = (pos as f32 + 0.5 - start) / zoom;
+    (src.max(0.0) as usize).min(len.saturating_sub(1))
+}
+
+/// Multiplies every channel of a premultiplied pixel by `opacity`/255
+fn scale(pixel: u32, opacity: u32) -> u32 {
+    if opacity >= 255 {
+        return pixel;
+    }
+    let mut out = 0;
+    for shift in [0, 8, 16, 24] {
+        out |= (((pixel >> shift) & 0xff) * opacity / 255) << shift;
+    }
+    out
+}
+
+/// Like `scale`, leaving the alpha channel unchanged
+fn scale_rgb(pixel: u32, factor: u32) -> u32 {
+    scale(pixel & 0x00ff_ffff, factor) | (pixel & 0xff00_0000)
+}
+
+/// Applies colour filters to a premultiplied pixel
+fn filtered(pixel: u32, matrix: &ColorMatrix) -> u32 {
+    if matrix.is_identity() {
+        return pixel;
+    }
+    let alpha = (pixel >> 24) as f32 / 255.0;
+    let rgb = [16, 8, 0].map(|shift| ((pixel >> shift) & 0xff) as f32 / 255.0);
+    let [r, g, b] =
+        matrix.apply(rgb, alpha).map(|c| (c * 255.0).round() as u32);
+    (pixel & 0xff00_0000) | r << 16 | g << 8 | b
+}
+
+/// Multiplies the colour of a pixel by `white_point`, channel by channel
+fn tint(pixel: u32, white_point: [f32; 3]) -> u32 {
+    let mut out = pixel & 0xff00_0000;
+    for (shift, factor) in [16, 8, 0].into_iter().zip(white_point) {
+        let channel = ((pixel >> shift) & 0xff) as f32 * factor;
+        out |= (channel.round() as u32).min(255) << shift;
+    }
+    out
+}
+
+/// Interpolates from `from` to `to` by `amount`/255
+fn mix(from: u32, to: u32, amount: u32) -> u32 {
+    scale(from, 255 - amount.min(255)) + scale(to, amount)
+}
+
+/// Porter-Duff OVER for premultiplied pixels
+fn blend_over(src: u32, dst: u32) -> u32 {
+    let inv_alpha = 255 - (src >> 24);
+    let mut out = 0;
+    for shift in [0, 8, 16, 24] {
+        let s = (src >> shift) & 0xff;
+        let d = (dst >> shift) & 0xff;
+        out |= (s + d * inv_alpha / 255).min(255) << shift;
+    }
+    out
+}
@@ -1,18 +1,37 @@
 extern crate gl;
 extern crate x11;
 
+mod blur;
 pub mod buffer;
+mod custom;
+mod framebuffer;
+mod post;
 pub mod setup;
 mod shader;
 pub mod texture;
 
+use super::Backend;
+use crate::config::{
+    Config, CornerConfig, Filter, ShadowConfig, SwapSync, Vsync,
+};
+use crate::filter::{ColorFilter, ColorMatrix};
+use crate::lut;
+use crate::region::{self, Region};
+use crate::shadow::Mask;
 use crate::state::State;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
 use std::ffi::CString;
 use std::os::raw::c_ulong;
+use std::path::PathBuf;
+use std::time::Instant;
 
 use crate::window::Window;
+use blur::{Blur, Clip};
 use buffer::{Buffer, ElementBuffer, VertexArray};
-use shader::{Program, Shader};
+use custom::CustomShader;
+use post::Pipeline;
+use shader::{Program, Shader, Uniform};
 use texture::Texture;
 use x11::{glx::*, xlib};
 
@@ -23,14 +42,76 @@ const GLX_TEXTURE_FORMAT_EXT: i32 = 0x20D5;
 const GLX_TEXTURE_FORMAT_RGBA_EXT: i32 = 0x20DA;
 const GLX_FRONT_LEFT_EXT: i32 = 0x20DE;
 
+/// Longest wait for a fence before swapping buffers, in nanoseconds
+const FENCE_TIMEOUT: u64 = 100_000_000;
+
+/// Oldest back buffer that gets the damage since then drawn over it,
+/// older ones are redrawn whole
+const MAX_BUFFER_AGE: usize = 4;
+
 pub struct Opengl<'a> {
     pub ctx: *mut __GLXcontextRec,
     pub conn: &'a xcb::Connection,
     pub dpy: *mut xlib::Display,
     pub target_win: xlib::XID,
+    /// Number of screens(xlib)
+    pub screens: i32,
     pub fbconfig: GLXFBConfig,
     pub root_data_vbo: Buffer,
     pub root_texture: Texture,
+    /// Texture filtering for window textures
+    pub filter: gl::types::GLint,
+    /// Darkening of unfocused windows, from the config
+    inactive_dim: f32,
+    shadow: ShadowConfig,
+    corners: CornerConfig,
+    /// Root window, outputs are looked up on it
+    root: xcb::Window,
+    /// Root window size, used when no screen size is configured
+    root_size: [u16; 2],
+    /// Size of the area windows are drawn on
+    screen_size: [u16; 2],
+    program: Program,
+    screen_dim: Uniform,
+    opacity: Uniform,
+    dim: Uniform,
+    color_matrix: Uniform,
+    color_offset: Uniform,
+    size: Uniform,
+    radius: Uniform,
+    border_width: Uniform,
+    border_color: Uniform,
+    shadow_program: Program,
+    shadow_screen_dim: Uniform,
+    shadow_color: Uniform,
+    blur: Blur,
+    /// Kept to link the custom shaders with
+    window_vert: Shader,
+    /// Custom shaders set by rules that compiled, by path
+    custom: HashMap<PathBuf, CustomShader>,
+    /// Origin of the `time` uniform of custom shaders
+    start: Instant,
+    /// Passes run over every frame, drawn offscreen when there are any
+    post: Pipeline,
+    /// Whether the root has been drawn for the frame in progress
+    frame_started: Cell<bool>,
+    /// Parts of the framebuffer the frame in progress is limited to, as
+    /// [x, y, width, height] rects from the bottom left, each drawn with
+    /// its own scissor. All of it when `None`.
+    damage: RefCell<Option<Vec<[i32; 4]>>>,
+    /// Parts of the framebuffer that changed since the last frame, as
+    /// above
+    changed: RefCell<Option<Vec<[i32; 4]>>>,
+    /// What changed in the last frames, newest first. Emptied when the
+    /// buffers drawn to stop holding these frames.
+    history: RefCell<VecDeque<Option<Vec<[i32; 4]>>>>,
+    /// Whether GLX_EXT_buffer_age tells how old the back buffer is,
+    /// without it frames drawn to the window are drawn whole
+    buffer_age: bool,
+    /// Set when vsync waits for vertical blanks itself
+    video_sync: Option<setup::VideoSync>,
+    /// What is waited for before swapping buffers
+    swap_sync: SwapSync,
 
     // I don't yet know enough Rust to do this any other way
     glx_bind_tex_image: setup::GLXBindTexImageEXT,
@@ -41,7 +122,10 @@ pub struct Opengl<'a> {
 }
 
 impl<'a> Opengl<'a> {
-    pub fn init(state: &State) -> Result<Opengl, &str> {
+    pub fn init<'b>(
+        state: &'b State,
+        config: &Config,
+    ) -> Result<Opengl<'b>, &'b str> {
         setup::verify_extensions(&state.conn, state.xlib_screens)?;
         let raw_dpy = state.conn.get_raw_dpy();
         // setup framebuffer context
@@ -88,7 +172,11 @@ impl<'a> Opengl<'a> {
         if !gl::GenVertexArrays::is_loaded() {
             return Err("no GL3 support available!");
         }
-        let ctx = setup::create_glx_context(&state.conn, fbconfig)?;
+        let ctx = setup::create_glx_context(
+            &state.conn,
+            fbconfig,
+            config.opengl.context_version,
+        )?;
 
         unsafe {
             // Set ctx as the current one used for drawing
@@ -97,6 +185,12 @@ impl<'a> Opengl<'a> {
             gl::Enable(gl::BLEND);
             gl::BlendFunc(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
         }
+        let video_sync = set_vsync(
+            raw_dpy,
+            state.xlib_screens,
+            state.overlay as xlib::XID,
+            config.vsync,
+        );
 
         // Load shaders
         let vert = Shader::from_vert_source(
@@ -107,11 +201,34 @@ impl<'a> Opengl<'a> {
             &CString::new(include_str!("opengl/window.frag")).unwrap(),
         )
         .unwrap();
-        let shader_program = Program::from_shaders(&[vert, frag]).unwrap();
-        // TODO: Provide screen dimensions
-        let screen_dim = shader_program.create_uniform("screenDim");
-        shader_program.set_used();
-        screen_dim.data_2f(&[1366.0, 768.0]);
+        let program = Program::from_shaders(&[&vert, &frag]).unwrap();
+        let shadow_frag = Shader::from_frag_source(
+            &CString::new(include_str!("opengl/shadow.frag")).unwrap(),
+        )
+        .unwrap();
+        let shadow_program =
+            Program::from_shaders(&[&vert, &shadow_frag]).unwrap();
+        let root_size = [state.root.width, state.root.height];
+        let screen_size = config.screen_size.unwrap_or(root_size);
+        let [width, height] = screen_size;
+        let screen_dim = program.create_uniform("screenDim");
+        program.set_used();
+        screen_dim.data_2f(&[width as f32, height as f32]);
+        let opacity = program.create_uniform("opacity");
+        let dim = program.create_uniform("dim");
+        let color_matrix = program.create_uniform("colorMatrix");
+        let color_offset = program.create_uniform("colorOffset");
+        let size = program.create_uniform("size");
+        let radius = program.create_uniform("radius");
+        let border_width = program.create_uniform("borderWidth");
+        let border_color = program.create_uniform("borderColor");
+        let shadow_screen_dim = shadow_program.create_uniform("screenDim");
+        let shadow_color = shadow_program.create_uniform("color");
+        shadow_program.set_used();
+        shadow_screen_dim.data_2f(&[width as f32, height as f32]);
+        program.set_used();
+        border_color.data_3f(&config.corners.border_color.to_f32());
+        let filter = gl_filter(config.opengl.filter);
 
         // Vertex object and array
         let vao = VertexArray::new();
@@ -126,6 +243,12 @@ impl<'a> Opengl<'a> {
         ebo.bind();
         ebo.load_data(&[0, 1, 2, 1, 2, 3]);
 
+        let blur = Blur::new(root_size, config.blur.strength);
+        let custom = custom::load_all(shader_paths(config), &vert);
+        let corrections =
+            lut::load(&state.conn, state.root.id, &config.lut, root_size);
+        let post = Pipeline::new(root_size, config, corrections);
+
         // create root texture from pixmap
         let root_texture = {
             let root_glxpixmap = unsafe {
@@ -153,7 +276,7 @@ impl<'a> Opengl<'a> {
                     std::ptr::null(),
                 );
             }
-            set_tex_params();
+            set_tex_params(filter);
             texture
         };
 
@@ -170,9 +293,42 @@ impl<'a> Opengl<'a> {
             conn: &state.conn,
             dpy: raw_dpy,
             target_win: state.overlay as xlib::XID,
+            screens: state.xlib_screens,
             fbconfig,
             root_texture,
             root_data_vbo,
+            filter,
+            inactive_dim: config.inactive_dim,
+            shadow: config.shadow.clone(),
+            corners: config.corners.clone(),
+            root: state.root.id,
+            root_size,
+            screen_size,
+            program,
+            screen_dim,
+            opacity,
+            dim,
+            color_matrix,
+            color_offset,
+            size,
+            radius,
+            border_width,
+            border_color,
+            shadow_program,
+            shadow_screen_dim,
+            shadow_color,
+            blur,
+            window_vert: vert,
+            custom,
+            start: Instant::now(),
+            post,
+            frame_started: Cell::new(false),
+            damage: RefCell::new(None),
+            changed: RefCell::new(None),
+            history: RefCell::new(VecDeque::new()),
+            buffer_age: setup::has_buffer_age(raw_dpy, state.xlib_screens),
+            video_sync,
+            swap_sync: config.opengl.swap_sync,
             glx_bind_tex_image,
             glx_release_tex_image,
 
@@ -182,15 +338,19 @@ impl<'a> Opengl<'a> {
             _ebo: ebo,
         })
     }
+}
+
+impl<'a> Backend for Opengl<'a> {
+    type Context = BackendContext;
 
-    pub fn init_window(&self, win: &mut Window) {
+    fn init_window(&self, win: &mut Window<BackendContext>) {
         // No point in creating texture or glxpixmap since they have
         // to be re created for every update
         win.context.vbo = Buffer::new();
         win.context.load_buffer(win);
     }
 
-    pub fn update_pixmap(&self, win: &mut Window) {
+    fn update_pixmap(&self, win: &mut Window<BackendContext>) {
         // The texture is only updated on `update_texture`
         // so no need to bind yet
         win.context.texture = Texture::new();
@@ -220,11 +380,15 @@ impl<'a> Opengl<'a> {
         };
     }
 
-    pub fn update_pos(&self, win: &Window) {
+    fn update_pos(&self, win: &Window<BackendContext>) {
         win.context.update_buffer(win);
+        if let Some(shadow) = &win.context.shadow {
+            shadow.vbo.bind();
+            shadow.vbo.update_data(&self.shadow_data(win));
+        }
     }
 
-    pub fn update_texture(&self, win: &mut Window) {
+    fn update_texture(&self, win: &mut Window<BackendContext>) {
         win.context.texture.bind();
         unsafe {
             (self.glx_bind_tex_image)(
@@ -235,46 +399,419 @@ impl<'a> Opengl<'a> {
             );
         }
         // Set texture parameters
-        set_tex_params();
+        set_tex_params(self.filter);
+    }
+
+    fn update_masks(&self, win: &mut Window<BackendContext>) {
+        if !win.has_shadow(self.shadow.enabled) {
+            win.context.shadow = None;
+            return;
+        }
+        let corner_radius = win.corner_radius(self.corners.radius);
+        let mask = Mask::build(win, self.shadow.radius, corner_radius);
+        let texture = Texture::new();
+        texture.bind();
+        unsafe {
+            // Rows of the mask are not padded
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::R8 as i32,
+                mask.width as i32,
+                mask.height as i32,
+                0,
+                gl::RED,
+                gl::UNSIGNED_BYTE,
+                mask.data.as_ptr() as *const gl::types::GLvoid,
+            );
+        }
+        set_tex_params(gl::LINEAR as i32);
+        let vbo = Buffer::new();
+        vbo.bind();
+        vbo.load_data(&self.shadow_data(win));
+        win.context.shadow = Some(Shadow { texture, vbo });
+    }
+
+    fn set_damage(&self, region: Region, windows: &[Window<BackendContext>]) {
+        let rects = match region {
+            Region::Rects(rects) => rects,
+            Region::Full => return,
+        };
+        let mut changed = Vec::new();
+        for rect in rects {
+            // Rounding can make neighbours overlap
+            region::insert(
+                &mut changed,
+                self.framebuffer_rect(rect.map(|v| v as f32)),
+            );
+        }
+        let changed = self.blur_extent(changed, windows);
+        self.changed.replace(Some(changed.clone()));
+        // The buffer drawn to also misses what changed since it was drawn
+        if let Some(stale) = self.stale_area(changed) {
+            self.damage.replace(Some(self.blur_extent(stale, windows)));
+        }
     }
 
-    pub fn draw_window(&self, window: &Window) {
+    fn draw_window(&self, window: &Window<BackendContext>) {
+        self.start_frame();
+        if let Some(shadow) = &window.context.shadow {
+            let [r, g, b] = self.shadow.color.to_f32();
+            let alpha = self.shadow.opacity * window.draw_opacity();
+            self.shadow_program.set_used();
+            self.shadow_color.data_4f(&[
+                r * alpha,
+                g * alpha,
+                b * alpha,
+                alpha,
+            ]);
+            shadow.vbo.bind();
+            VertexArray::attrib_pointer(0, 2, 4, 0);
+            VertexArray::attrib_pointer(1, 2, 4, 2);
+            shadow.texture.bind();
+            self.draw_quad();
+            self.program.set_used();
+        }
+        if window.has_blur() {
+            self.blur_behind(window);
+        }
         window.context.vbo.bind();
         // TODO: OpenGl 4.3 has glBindVertexBuffers
         VertexArray::attrib_pointer(0, 2, 4, 0);
         VertexArray::attrib_pointer(1, 2, 4, 2);
         window.context.texture.bind();
-        unsafe {
-            gl::DrawElements(
-                gl::TRIANGLES,
-                6,
-                gl::UNSIGNED_INT,
-                std::ptr::null(),
-            );
-            // TODO: check for performance impact of this line
-            //setup::check_gl_error();
+        if let Some(custom) =
+            window.effects.shader.as_ref().and_then(|p| self.custom.get(p))
+        {
+            self.draw_custom(window, custom);
+            return;
         }
+        self.opacity.data_1f(window.draw_opacity());
+        self.dim.data_1f(if window.dimmed() {
+            self.inactive_dim
+        } else {
+            0.0
+        });
+        self.set_color_matrix(window.color_matrix());
+        self.size.data_2f(&[
+            window.outer_width() as f32,
+            window.outer_height() as f32,
+        ]);
+        self.radius.data_1f(window.corner_radius(self.corners.radius) as f32);
+        self.border_width.data_1f(if window.rounded() {
+            self.corners.border_width as f32
+        } else {
+            0.0
+        });
+        self.draw_quad();
+        // TODO: check for performance impact of this line
+        //setup::check_gl_error();
     }
 
-    pub fn render(&self) {
+    fn render(&self) {
+        self.start_frame();
+        unsafe { gl::Disable(gl::SCISSOR_TEST) };
+        self.post.run();
+        match self.swap_sync {
+            SwapSync::None => (),
+            SwapSync::Finish => unsafe { gl::Finish() },
+            SwapSync::Fence => unsafe {
+                let fence = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+                gl::ClientWaitSync(
+                    fence,
+                    gl::SYNC_FLUSH_COMMANDS_BIT,
+                    FENCE_TIMEOUT,
+                );
+                gl::DeleteSync(fence);
+            },
+        }
+        if let Some(video_sync) = &self.video_sync {
+            video_sync.wait();
+        }
         unsafe {
             glXSwapBuffers(self.dpy, self.target_win);
-            gl::Clear(gl::COLOR_BUFFER_BIT);
-            // draw root window
-            self.root_data_vbo.bind();
-            VertexArray::attrib_pointer(0, 2, 4, 0);
-            VertexArray::attrib_pointer(1, 2, 4, 2);
-            self.root_texture.bind();
+        }
+        let mut history = self.history.borrow_mut();
+        history.push_front(self.changed.take());
+        history.truncate(MAX_BUFFER_AGE);
+        self.frame_started.set(false);
+        self.damage.replace(None);
+    }
+
+    fn reconfigure(&mut self, config: &Config) {
+        self.filter = gl_filter(config.opengl.filter);
+        self.inactive_dim = config.inactive_dim;
+        self.screen_size = config.screen_size.unwrap_or(self.root_size);
+        let [width, height] = self.screen_size;
+        if config.blur.strength != self.blur.passes() {
+            self.blur = Blur::new(self.root_size, config.blur.strength);
+        }
+        self.shadow = config.shadow.clone();
+        self.corners = config.corners.clone();
+        // Reloading the config also picks up edits to the shaders
+        self.custom =
+            custom::load_all(shader_paths(config), &self.window_vert);
+        let corrections =
+            lut::load(self.conn, self.root, &config.lut, self.root_size);
+        self.post = Pipeline::new(self.root_size, config, corrections);
+        self.shadow_program.set_used();
+        self.shadow_screen_dim.data_2f(&[width as f32, height as f32]);
+        self.program.set_used();
+        self.screen_dim.data_2f(&[width as f32, height as f32]);
+        self.border_color.data_3f(&self.corners.border_color.to_f32());
+        // The new scene starts empty, the frame being drawn restarts whole
+        self.frame_started.set(false);
+        self.damage.replace(None);
+        self.changed.replace(None);
+        self.clear_history();
+        self.swap_sync = config.opengl.swap_sync;
+        self.video_sync =
+            set_vsync(self.dpy, self.screens, self.target_win, config.vsync);
+    }
+
+    fn update_outputs(&mut self, config: &Config) {
+        let corrections =
+            lut::load(self.conn, self.root, &config.lut, self.root_size);
+        self.post.set_corrections(corrections);
+        self.frame_started.set(false);
+        self.clear_history();
+    }
+
+    fn set_color_filters(&mut self, filters: &[ColorFilter]) {
+        self.post.set_color_matrix(ColorMatrix::new(filters));
+        // Creating targets binds the window framebuffer, and frames go
+        // through the scene only with passes
+        self.frame_started.set(false);
+        self.clear_history();
+    }
+}
+
+impl<'a> Opengl<'a> {
+    /// Sets the colour filters of the window drawn next
+    fn set_color_matrix(&self, matrix: ColorMatrix) {
+        self.color_matrix.data_matrix_3f(&matrix.linear_part());
+        self.color_offset.data_3f(&matrix.offset());
+    }
+
+    /// Starts the frame in progress unless done already: binds the scene,
+    /// limits drawing to the damage and draws the root window
+    fn start_frame(&self) {
+        if self.frame_started.replace(true) {
+            return;
+        }
+        self.post.bind_scene();
+        self.program.set_used();
+        if self.damage.borrow().is_some() {
+            unsafe { gl::Enable(gl::SCISSOR_TEST) };
+        }
+        self.draw_root();
+    }
+
+    /// Runs `draw` once for every rect of the damage, with the scissor
+    /// set to it, or once when the frame is drawn whole
+    fn scissored(&self, draw: impl Fn()) {
+        match &*self.damage.borrow() {
+            Some(rects) => {
+                for &[x, y, width, height] in rects {
+                    unsafe { gl::Scissor(x, y, width, height) };
+                    draw();
+                }
+            }
+            None => draw(),
+        }
+    }
+
+    /// Draws the bound quad over the damage
+    fn draw_quad(&self) {
+        self.scissored(|| unsafe {
             gl::DrawElements(
                 gl::TRIANGLES,
                 6,
                 gl::UNSIGNED_INT,
                 std::ptr::null(),
             );
-            // TODO: check for performance impact of this line
-            //setup::check_gl_error();
+        });
+    }
+
+    /// `area` grown to hold all of what blur behind windows in it reads.
+    /// What is behind them has to be redrawn before they are.
+    fn blur_extent(
+        &self,
+        mut area: Vec<[i32; 4]>,
+        windows: &[Window<BackendContext>],
+    ) -> Vec<[i32; 4]> {
+        let margin = self.blur.margin();
+        let blurred: Vec<[i32; 4]> = windows
+            .iter()
+            .filter(|w| w.visible() && w.has_blur())
+            .map(|w| {
+                self.framebuffer_rect(w.draw_rect(
+                    0.0,
+                    0.0,
+                    w.outer_width() as f32,
+                    w.outer_height() as f32,
+                ))
+            })
+            .collect();
+        loop {
+            let before = area.clone();
+            for &rect in &blurred {
+                let overlaps = area
+                    .iter()
+                    .any(|&a| region::intersection(a, rect).is_some());
+                if overlaps {
+                    region::insert(&mut area, region::expand(rect, margin));
+                }
+            }
+            if area == before {
+                return area;
+            }
         }
     }
+
+    /// What the buffer drawn to lacks to show the frame in progress,
+    /// `changed` being what differs from the last frame. `None` when it
+    /// has to be drawn whole.
+    fn stale_area(&self, changed: Vec<[i32; 4]>) -> Option<Vec<[i32; 4]>> {
+        let age = match self.post.scene() {
+            // Holds the last frame
+            Some(_) => 1,
+            None if self.buffer_age => {
+                setup::buffer_age(self.dpy, self.target_win) as usize
+            }
+            None => 0,
+        };
+        missed_changes(&self.history.borrow(), age, changed)
+    }
+
+    /// Forgets the frames drawn so far, the next ones being drawn whole
+    fn clear_history(&self) {
+        self.history.borrow_mut().clear();
+    }
+
+    /// Pixels of the framebuffer covering `rect` of the screen area, as
+    /// [x, y, width, height] from the bottom left
+    fn framebuffer_rect(&self, rect: [f32; 4]) -> [i32; 4] {
+        // The screen area is stretched over the framebuffer
+        let scale_x = self.root_size[0] as f32 / self.screen_size[0] as f32;
+        let scale_y = self.root_size[1] as f32 / self.screen_size[1] as f32;
+        let [x, y, width, height] = rect;
+        region::enclosing([
+            x * scale_x,
+            self.root_size[1] as f32 - (y + height) * scale_y,
+            width * scale_x,
+            height * scale_y,
+        ])
+    }
+
+    /// Clears the bound framebuffer and draws the root window, which
+    /// starts every frame
+    fn draw_root(&self) {
+        self.scissored(|| unsafe { gl::Clear(gl::COLOR_BUFFER_BIT) });
+        self.opacity.data_1f(1.0);
+        self.dim.data_1f(0.0);
+        self.set_color_matrix(ColorMatrix::IDENTITY);
+        self.size
+            .data_2f(&[self.root_size[0] as f32, self.root_size[1] as f32]);
+        self.radius.data_1f(0.0);
+        self.border_width.data_1f(0.0);
+        self.root_data_vbo.bind();
+        VertexArray::attrib_pointer(0, 2, 4, 0);
+        VertexArray::attrib_pointer(1, 2, 4, 2);
+        self.root_texture.bind();
+        self.draw_quad();
+    }
+
+    /// Draws `window`, with its buffers bound, using a custom shader
+    fn draw_custom(
+        &self,
+        window: &Window<BackendContext>,
+        custom: &CustomShader,
+    ) {
+        let [width, height] = self.screen_size;
+        custom.program.set_used();
+        custom.screen_dim.data_2f(&[width as f32, height as f32]);
+        custom.opacity.data_1f(window.draw_opacity());
+        custom.size.data_2f(&[
+            window.outer_width() as f32,
+            window.outer_height() as f32,
+        ]);
+        custom.time.data_1f(self.start.elapsed().as_secs_f32());
+        self.draw_quad();
+        self.program.set_used();
+    }
+
+    /// Blurs what is drawn behind `win`
+    fn blur_behind(&self, win: &Window<BackendContext>) {
+        // Windows are placed on the screen area, which is stretched over
+        // the framebuffer
+        let scale_x = self.root_size[0] as f32 / self.screen_size[0] as f32;
+        let scale_y = self.root_size[1] as f32 / self.screen_size[1] as f32;
+        let rects: Vec<[i32; 4]> = win
+            .blur_rects()
+            .iter()
+            .map(|r| {
+                self.framebuffer_rect(win.draw_rect(
+                    r.x() as f32,
+                    r.y() as f32,
+                    r.width() as f32,
+                    r.height() as f32,
+                ))
+            })
+            .flat_map(|rect| match &*self.damage.borrow() {
+                Some(damage) => damage
+                    .iter()
+                    .filter_map(|&d| region::intersection(rect, d))
+                    .collect(),
+                None => vec![rect],
+            })
+            .collect();
+        if rects.is_empty() {
+            return;
+        }
+        let corner_radius = win.corner_radius(self.corners.radius);
+        let clip = (corner_radius > 0).then(|| {
+            let [x, y, width, height] = win.draw_rect(
+                0.0,
+                0.0,
+                win.outer_width() as f32,
+                win.outer_height() as f32,
+            );
+            let scale = win.transform().scale * scale_x.min(scale_y);
+            Clip {
+                bounds: [
+                    x * scale_x,
+                    self.root_size[1] as f32 - (y + height) * scale_y,
+                    width * scale_x,
+                    height * scale_y,
+                ],
+                radius: corner_radius as f32 * scale,
+            }
+        });
+        // Its passes draw whole textures, the damage is applied through
+        // `rects` instead
+        unsafe { gl::Disable(gl::SCISSOR_TEST) };
+        self.blur.apply(self.post.scene(), &rects, clip.as_ref());
+        if self.damage.borrow().is_some() {
+            unsafe { gl::Enable(gl::SCISSOR_TEST) };
+        }
+        self.program.set_used();
+    }
+
+    /// Quad of the shadow mask, `radius` larger than the window on every
+    /// side and moved by the offset
+    fn shadow_data(&self, win: &Window<BackendContext>) -> [f32; 16] {
+        let radius = self.shadow.radius as f32;
+        let [dx, dy] = self.shadow.offset;
+        let [x, y, width, height] = win.draw_rect(
+            dx as f32 - radius,
+            dy as f32 - radius,
+            win.outer_width() as f32 + radius * 2.0,
+            win.outer_height() as f32 + radius * 2.0,
+        );
+        quad_data(x, y, width, height)
+    }
 }
 
 impl<'a> Drop for Opengl<'a> {
@@ -289,65 +826,152 @@ pub struct BackendContext {
     pub glxpixmap: c_ulong,
     pub texture: Texture,
     pub vbo: Buffer,
+    pub shadow: Option<Shadow>,
+}
+
+/// Shadow mask of a window and its quad
+pub struct Shadow {
+    pub texture: Texture,
+    pub vbo: Buffer,
 }
 
 impl BackendContext {
-    pub fn load_buffer(&self, window: &Window) {
+    pub fn load_buffer(&self, window: &Window<BackendContext>) {
         // TODO: Look into geometry shader
         self.vbo.bind();
         self.vbo.load_data(&formatted_win_data(window));
     }
-    pub fn update_buffer(&self, window: &Window) {
+    pub fn update_buffer(&self, window: &Window<BackendContext>) {
         // TODO: Look into geometry shader
         self.vbo.bind();
         self.vbo.update_data(&formatted_win_data(window));
     }
 }
 
-fn set_tex_params() {
+/// Custom shaders set by the rules of `config`
+fn shader_paths(config: &Config) -> impl Iterator<Item = &PathBuf> {
+    config.rules.iter().filter_map(|rule| rule.set.shader.as_ref())
+}
+
+/// Sets up `vsync`, reporting failures
+fn set_vsync(
+    dpy: *mut xlib::Display,
+    screens: i32,
+    drawable: xlib::XID,
+    vsync: Vsync,
+) -> Option<setup::VideoSync> {
+    setup::set_vsync(dpy, screens, drawable, vsync).unwrap_or_else(|err| {
+        warn!("Unable to set vsync: {}", err);
+        None
+    })
+}
+
+fn gl_filter(filter: Filter) -> gl::types::GLint {
+    match filter {
+        Filter::Nearest => gl::NEAREST as i32,
+        Filter::Linear => gl::LINEAR as i32,
+    }
+}
+
+fn set_tex_params(filter: gl::types::GLint) {
     unsafe {
-        gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MIN_FILTER,
-            gl::NEAREST as i32,
-        );
-        gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MAG_FILTER,
-            gl::NEAREST as i32,
-        );
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter);
     }
 }
 
-fn formatted_win_data(window: &Window) -> [f32; 16] {
+fn formatted_win_data<C: Default>(window: &Window<C>) -> [f32; 16] {
+    let [x, y, width, height] = window.draw_rect(
+        0.0,
+        0.0,
+        window.outer_width() as f32,
+        window.outer_height() as f32,
+    );
+    quad_data(x, y, width, height)
+}
+
+/// Position and texture coordinates of the corners of a rectangle
+fn quad_data(x: f32, y: f32, width: f32, height: f32) -> [f32; 16] {
     [
         // top left
-        (window.x as f32),
-        (window.y as f32),
+        x,
+        y,
         0.0,
         0.0,
         // top right
-        (window.x as f32
-            + window.width as f32
-            + window.border_width as f32 * 2.0),
-        (window.y as f32),
+        x + width,
+        y,
         1.0,
         0.0,
         // bottom left
-        (window.x as f32),
-        (window.y as f32
-            + window.height as f32
-            + window.border_width as f32 * 2.0),
+        x,
+        y + height,
         0.0,
         1.0,
         // bottom right
-        (window.x as f32
-            + window.width as f32
-            + window.border_width as f32 * 2.0),
-        (window.y as f32
-            + window.height as f32
-            + window.border_width as f32 * 2.0),
+        x + width,
+        y + height,
         1.0,
         1.0,
     ]
 }
+
+/// `changed` along with what changed in the frames drawn since the
+/// buffer, `age` frames old, was drawn. `None` when it is too old or
+/// of unknown age for `history`, which lists what changed newest first.
+fn missed_changes(
+    history: &VecDeque<Option<Vec<[i32; 4]>>>,
+    age: usize,
+    changed: Vec<[i32; 4]>,
+) -> Option<Vec<[i32; 4]>> {
+    if age == 0 || age > history.len() {
+        return None;
+    }
+    history.iter().take(age - 1).try_fold(changed, |mut area, rects| {
+        for &rect in rects.as_ref()? {
+            region::insert(&mut area, rect);
+        }
+        Some(area)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> VecDeque<Option<Vec<[i32; 4]>>> {
+        VecDeque::from([
+            Some(vec![[0, 0, 10, 10]]),
+            Some(vec![[50, 50, 10, 10]]),
+            None,
+            Some(vec![[100, 0, 10, 10]]),
+        ])
+    }
+
+    #[test]
+    fn redraws_changes_of_the_last_frame_only() {
+        let changed = vec![[20, 20, 5, 5]];
+        assert_eq!(
+            missed_changes(&history(), 1, changed.clone()),
+            Some(changed)
+        );
+    }
+
+    #[test]
+    fn adds_changes_of_older_frames() {
+        let changed = vec![[20, 20, 5, 5]];
+        assert_eq!(
+            missed_changes(&history(), 3, changed),
+            Some(vec![[20, 20, 5, 5], [0, 0, 10, 10], [50, 50, 10, 10]])
+        );
+    }
+
+    #[test]
+    fn redraws_whole_buffers_of_unknown_content() {
+        let changed = vec![[20, 20, 5, 5]];
+        // Undefined contents, older than the history, or drawn whole
+        assert_eq!(missed_changes(&history(), 0, changed.clone()), None);
+        assert_eq!(missed_changes(&history(), 5, changed.clone()), None);
+        assert_eq!(missed_changes(&history(), 4, changed), None);
+    }
+}
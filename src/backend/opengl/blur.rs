@@ -0,0 +1,186 @@
+extern crate gl;
+
+use super::buffer::{Buffer, VertexArray};
+use super::framebuffer::Framebuffer;
+use super::shader::{Program, Shader, Uniform};
+use super::texture::Texture;
+use std::ffi::CString;
+
+/// Dual-Kawase blur of what has been drawn so far.
+///
+/// Every pass halves the size of the image, then the same number of
+/// passes scale it back up, so `passes` sets the blur strength.
+pub struct Blur {
+    /// Size of the window framebuffer
+    size: [i32; 2],
+    /// Level 0 holds the copy of the framebuffer, each following one is
+    /// half the size of the previous
+    levels: Vec<Level>,
+    down: Program,
+    down_halfpixel: Uniform,
+    up: Program,
+    up_halfpixel: Uniform,
+    up_bounds: Uniform,
+    up_radius: Uniform,
+    /// Quad covering the whole target, in NDC
+    quad: Buffer,
+}
+
+/// Rounded rectangle the blur gets clipped to, in framebuffer coordinates
+pub struct Clip {
+    /// [x, y, width, height], origin at the bottom left
+    pub bounds: [f32; 4],
+    pub radius: f32,
+}
+
+struct Level {
+    texture: Texture,
+    framebuffer: Framebuffer,
+    size: [i32; 2],
+}
+
+impl Blur {
+    pub fn new(size: [u16; 2], passes: u8) -> Blur {
+        let vert = Shader::from_vert_source(
+            &CString::new(include_str!("fullscreen.vert")).unwrap(),
+        )
+        .unwrap();
+        let down_frag = Shader::from_frag_source(
+            &CString::new(include_str!("blur_down.frag")).unwrap(),
+        )
+        .unwrap();
+        let up_frag = Shader::from_frag_source(
+            &CString::new(include_str!("blur_up.frag")).unwrap(),
+        )
+        .unwrap();
+        let down = Program::from_shaders(&[&vert, &down_frag]).unwrap();
+        let up = Program::from_shaders(&[&vert, &up_frag]).unwrap();
+
+        let quad = Buffer::new();
+        quad.bind();
+        quad.load_data(&[
+            -1.0f32, 1.0, 0.0, 1.0, // top left
+            1.0, 1.0, 1.0, 1.0, // top right
+            -1.0, -1.0, 0.0, 0.0, // bottom left
+            1.0, -1.0, 1.0, 0.0, // bottom right
+        ]);
+
+        let size = [size[0] as i32, size[1] as i32];
+        let levels = (0..=passes)
+            .map(|i| {
+                let size = [(size[0] >> i).max(1), (size[1] >> i).max(1)];
+                let texture = Texture::new();
+                texture.allocate(size[0], size[1]);
+                let framebuffer = Framebuffer::new(&texture);
+                Level { texture, framebuffer, size }
+            })
+            .collect();
+
+        Blur {
+            size,
+            levels,
+            down_halfpixel: down.create_uniform("halfpixel"),
+            down,
+            up_halfpixel: up.create_uniform("halfpixel"),
+            up_bounds: up.create_uniform("bounds"),
+            up_radius: up.create_uniform("radius"),
+            up,
+            quad,
+        }
+    }
+
+    pub fn passes(&self) -> u8 {
+        (self.levels.len() - 1) as u8
+    }
+
+    /// How far from a pixel the blur reads, in framebuffer pixels. Each
+    /// pass down reads 1.5 texels of its source away, each pass up 2.
+    pub fn margin(&self) -> i32 {
+        6 << self.passes()
+    }
+
+    /// Blurs what has been drawn so far into `target`, the window
+    /// framebuffer when `None`, and draws it back inside `rects`, given as
+    /// [x, y, width, height] in framebuffer coordinates (origin at the
+    /// bottom left), and within `clip` when set.
+    ///
+    /// Leaves one of the blur programs in use and `target` bound.
+    pub fn apply(
+        &self,
+        target: Option<&Framebuffer>,
+        rects: &[[i32; 4]],
+        clip: Option<&Clip>,
+    ) {
+        let [width, height] = self.size;
+        self.levels[0].texture.bind();
+        unsafe {
+            gl::CopyTexSubImage2D(
+                gl::TEXTURE_2D,
+                0,
+                0,
+                0,
+                0,
+                0,
+                width,
+                height,
+            );
+            gl::Disable(gl::BLEND);
+        }
+        self.quad.bind();
+        VertexArray::attrib_pointer(0, 2, 4, 0);
+        VertexArray::attrib_pointer(1, 2, 4, 2);
+
+        self.down.set_used();
+        for pair in self.levels.windows(2) {
+            let (src, dst) = (&pair[0], &pair[1]);
+            self.down_halfpixel.data_2f(&halfpixel(src.size));
+            dst.framebuffer.bind();
+            draw(src, dst.size);
+        }
+
+        self.up.set_used();
+        self.up_radius.data_1f(-1.0);
+        for i in (1..self.levels.len()).rev() {
+            let src = &self.levels[i];
+            self.up_halfpixel.data_2f(&halfpixel(src.size));
+            if i > 1 {
+                let dst = &self.levels[i - 1];
+                dst.framebuffer.bind();
+                draw(src, dst.size);
+                continue;
+            }
+            // The last pass goes straight to the target, within `rects`
+            match target {
+                Some(framebuffer) => framebuffer.bind(),
+                None => Framebuffer::unbind(),
+            }
+            if let Some(clip) = clip {
+                self.up_bounds.data_4f(&clip.bounds);
+                self.up_radius.data_1f(clip.radius);
+            }
+            unsafe {
+                // Smoothed edges of the clip blend with what is behind
+                gl::Enable(gl::BLEND);
+                gl::Enable(gl::SCISSOR_TEST);
+            }
+            for [x, y, w, h] in rects {
+                unsafe { gl::Scissor(*x, *y, *w, *h) };
+                draw(src, self.size);
+            }
+            unsafe { gl::Disable(gl::SCISSOR_TEST) };
+        }
+    }
+}
+
+/// Draws `src` over the whole bound target
+fn draw(src: &Level, target_size: [i32; 2]) {
+    src.texture.bind();
+    unsafe {
+        gl::Viewport(0, 0, target_size[0], target_size[1]);
+        gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, std::ptr::null());
+    }
+}
+
+fn halfpixel(size: [i32; 2]) -> [f32; 2] {
+    [0.5 / size[0] as f32, 0.5 / size[1] as f32]
+}
@@ -0,0 +1,18 @@
+#version 330 core
+
+in vec2 Tex;
+
+uniform sampler2D texImage;
+// Half a texel of the source texture
+uniform vec2 halfpixel;
+
+// Dual-Kawase downsample
+void main()
+{
+    vec4 sum = texture(texImage, Tex) * 4.0;
+    sum += texture(texImage, Tex - halfpixel);
+    sum += texture(texImage, Tex + halfpixel);
+    sum += texture(texImage, Tex + vec2(halfpixel.x, -halfpixel.y));
+    sum += texture(texImage, Tex - vec2(halfpixel.x, -halfpixel.y));
+    gl_FragColor = sum / 8.0;
+}
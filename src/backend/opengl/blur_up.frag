@@ -0,0 +1,33 @@
+#version 330 core
+
+in vec2 Tex;
+
+uniform sampler2D texImage;
+// Half a texel of the source texture
+uniform vec2 halfpixel;
+// Rounded rectangle the result is clipped to, as x, y, width, height in
+// framebuffer pixels, and its corner radius. Not clipped when negative.
+uniform vec4 bounds;
+uniform float radius;
+
+// Dual-Kawase upsample
+void main()
+{
+    vec4 sum = texture(texImage, Tex + vec2(-halfpixel.x * 2.0, 0.0));
+    sum += texture(texImage, Tex + vec2(-halfpixel.x, halfpixel.y)) * 2.0;
+    sum += texture(texImage, Tex + vec2(0.0, halfpixel.y * 2.0));
+    sum += texture(texImage, Tex + vec2(halfpixel.x, halfpixel.y)) * 2.0;
+    sum += texture(texImage, Tex + vec2(halfpixel.x * 2.0, 0.0));
+    sum += texture(texImage, Tex + vec2(halfpixel.x, -halfpixel.y)) * 2.0;
+    sum += texture(texImage, Tex + vec2(0.0, -halfpixel.y * 2.0));
+    sum += texture(texImage, Tex + vec2(-halfpixel.x, -halfpixel.y)) * 2.0;
+    vec4 color = sum / 12.0;
+    if (radius >= 0.0) {
+        vec2 halfSize = bounds.zw / 2.0;
+        vec2 q = abs(gl_FragCoord.xy - bounds.xy - halfSize)
+            - (halfSize - radius);
+        float dist = length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - radius;
+        color *= clamp(0.5 - dist, 0.0, 1.0);
+    }
+    gl_FragColor = color;
+}
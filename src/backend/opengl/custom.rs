@@ -0,0 +1,154 @@
+extern crate gl;
+
+use super::shader::{Program, Shader, Uniform};
+use regex::Regex;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Fragment shader set by a rule, used instead of `window.frag`.
+///
+/// It gets the same `Tex` input and uniforms as the built-in shader:
+/// `texImage`, `opacity`, `size` (window size in pixels), `screenDim`
+/// (screen size in pixels), along with `time`, in seconds.
+pub struct CustomShader {
+    pub program: Program,
+    pub screen_dim: Uniform,
+    pub opacity: Uniform,
+    pub size: Uniform,
+    pub time: Uniform,
+}
+
+impl CustomShader {
+    /// Compiles the shader at `path` and links it with the window vertex
+    /// shader
+    pub fn load(path: &Path, vert: &Shader) -> Result<CustomShader, String> {
+        let program = compile_file(path, vert)?;
+        Ok(CustomShader {
+            screen_dim: program.create_uniform("screenDim"),
+            opacity: program.create_uniform("opacity"),
+            size: program.create_uniform("size"),
+            time: program.create_uniform("time"),
+            program,
+        })
+    }
+}
+
+/// Compiles the fragment shader at `path` and links it with `vert`.
+/// Errors point at lines of the file.
+pub fn compile_file(path: &Path, vert: &Shader) -> Result<Program, String> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let source = CString::new(source)
+        .map_err(|_| format!("{}: contains a nul byte", path.display()))?;
+    let frag = Shader::from_frag_source(&source)
+        .map_err(|log| locate_errors(path, &log))?;
+    Program::from_shaders(&[vert, &frag])
+        .map_err(|log| format!("{}: {}", path.display(), log.trim()))
+}
+
+/// Compiles every shader the rules refer to. Those that fail are
+/// reported and left out, so their windows use the built-in shader.
+pub fn load_all<'r>(
+    shaders: impl Iterator<Item = &'r PathBuf>,
+    vert: &Shader,
+) -> HashMap<PathBuf, CustomShader> {
+    let mut loaded = HashMap::new();
+    for path in shaders {
+        if loaded.contains_key(path) {
+            continue;
+        }
+        match CustomShader::load(path, vert) {
+            Ok(shader) => {
+                loaded.insert(path.clone(), shader);
+            }
+            Err(e) => {
+                error!(
+                    "Unable to compile shader, using the default one:\n{}",
+                    e
+                )
+            }
+        }
+    }
+    loaded
+}
+
+/// Rewrites the locations of a shader info log as `path:line:column`.
+///
+/// Drivers disagree on the format: Mesa writes `0:12(5): error: ...`,
+/// Nvidia `0(12) : error C0000: ...` and AMD `ERROR: 0:12: ...`.
+fn locate_errors(path: &Path, log: &str) -> String {
+    let location = Regex::new(
+        r"^(?:(ERROR|WARNING): )?\d+(?::(\d+)(?:\((\d+)\))?|\((\d+)\))\s*:\s*",
+    )
+    .unwrap();
+    log.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let captures = match location.captures(line) {
+                Some(captures) => captures,
+                None => return format!("{}: {}", path.display(), line),
+            };
+            let message = &line[captures[0].len()..];
+            let line_number = captures.get(2).or(captures.get(4)).unwrap();
+            let mut out =
+                format!("{}:{}", path.display(), line_number.as_str());
+            if let Some(column) = captures.get(3) {
+                out += &format!(":{}", column.as_str());
+            }
+            match captures.get(1) {
+                Some(level) => format!(
+                    "{}: {}: {}",
+                    out,
+                    level.as_str().to_lowercase(),
+                    message
+                ),
+                None => format!("{}: {}", out, message),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locate(log: &str) -> String {
+        locate_errors(Path::new("gray.frag"), log)
+    }
+
+    #[test]
+    fn locates_mesa_errors() {
+        assert_eq!(
+            locate("0:12(5): error: `color' undeclared\n"),
+            "gray.frag:12:5: error: `color' undeclared"
+        );
+    }
+
+    #[test]
+    fn locates_nvidia_errors() {
+        assert_eq!(
+            locate("0(7) : error C0000: syntax error"),
+            "gray.frag:7: error C0000: syntax error"
+        );
+    }
+
+    #[test]
+    fn locates_amd_errors() {
+        assert_eq!(
+            locate("ERROR: 0:3: 'x' : undeclared identifier\n"),
+            "gray.frag:3: error: 'x' : undeclared identifier"
+        );
+    }
+
+    #[test]
+    fn keeps_unlocated_lines() {
+        assert_eq!(
+            locate("0:1(1): warning: unused\n\nlink failed"),
+            "gray.frag:1:1: warning: unused\ngray.frag: link failed"
+        );
+    }
+}
@@ -0,0 +1,17 @@
+#version 330 core
+
+in vec2 Tex;
+
+uniform sampler2D texImage;
+// Colour filters, applied to premultiplied colours as
+// colorMatrix * rgb + colorOffset * alpha
+uniform mat3 colorMatrix;
+uniform vec3 colorOffset;
+
+void main()
+{
+    vec4 color = texture(texImage, Tex);
+    color.rgb = clamp(colorMatrix * color.rgb + colorOffset * color.a,
+        0.0, color.a);
+    gl_FragColor = color;
+}
@@ -0,0 +1,42 @@
+extern crate gl;
+
+use super::texture::Texture;
+
+/// Offscreen render target drawing into a texture
+pub struct Framebuffer {
+    id: gl::types::GLuint,
+}
+
+impl Framebuffer {
+    pub fn new(texture: &Texture) -> Framebuffer {
+        let mut id: gl::types::GLuint = 0;
+        unsafe {
+            gl::GenFramebuffers(1, &mut id);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, id);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                texture.id,
+                0,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        Framebuffer { id }
+    }
+    pub fn bind(&self) {
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, self.id) }
+    }
+    /// Binds the window framebuffer back
+    pub fn unbind() {
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, 0) }
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.id);
+        }
+    }
+}
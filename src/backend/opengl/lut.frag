@@ -0,0 +1,35 @@
+#version 330 core
+
+in vec2 Tex;
+
+uniform sampler2D texImage;
+uniform sampler3D lut;
+// Number of entries along each axis of the LUT
+uniform float lutSize;
+// Input colours mapped to the first and last entries
+uniform vec3 domainMin;
+uniform vec3 domainMax;
+// Parts of the frame the LUT applies to, [x, y, width, height] with the
+// origin at the bottom left
+uniform vec4 regions[16];
+uniform int regionCount;
+
+void main()
+{
+    vec4 color = texture(texImage, Tex);
+    bool inside = false;
+    for (int i = 0; i < regionCount; i++) {
+        vec2 pos = gl_FragCoord.xy - regions[i].xy;
+        inside = inside || (all(greaterThanEqual(pos, vec2(0.0)))
+            && all(lessThan(pos, regions[i].zw)));
+    }
+    if (!inside) {
+        gl_FragColor = color;
+        return;
+    }
+    vec3 coord = clamp((color.rgb - domainMin) / (domainMax - domainMin),
+        0.0, 1.0);
+    // Entries sit at the center of the texels
+    coord = coord * (lutSize - 1.0) / lutSize + 0.5 / lutSize;
+    gl_FragColor = vec4(texture(lut, coord).rgb, color.a);
+}
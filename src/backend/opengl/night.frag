@@ -0,0 +1,13 @@
+#version 330 core
+
+in vec2 Tex;
+
+uniform sampler2D texImage;
+// Colour white gets turned into at the current temperature
+uniform vec3 whitePoint;
+
+void main()
+{
+    vec4 color = texture(texImage, Tex);
+    gl_FragColor = vec4(color.rgb * whitePoint, color.a);
+}
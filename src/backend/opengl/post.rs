@@ -0,0 +1,293 @@
+extern crate gl;
+
+use super::buffer::{Buffer, VertexArray};
+use super::custom;
+use super::framebuffer::Framebuffer;
+use super::shader::{Program, Shader, Uniform};
+use super::texture::Texture;
+use crate::config::{Config, NightLightConfig};
+use crate::filter::ColorMatrix;
+use crate::lut::Correction;
+use crate::night;
+use std::ffi::CString;
+use std::time::Instant;
+
+/// Chain of full-screen passes run over the composited frame before it
+/// gets presented.
+///
+/// With passes, windows are drawn into an offscreen scene, which keeps
+/// the last frame so that only the damaged parts of the next one need to
+/// be drawn. The first pass reads it, every other one the output of the
+/// previous one, and the last one draws to the window. Without any,
+/// frames are drawn to the window directly.
+pub struct Pipeline {
+    size: [i32; 2],
+    passes: Vec<Pass>,
+    scene: Option<Target>,
+    /// Output of every pass but the last, alternating
+    targets: Vec<Target>,
+    /// Quad covering the whole target, in NDC
+    quad: Buffer,
+    start: Instant,
+    night_light: NightLightConfig,
+    /// Kept to link the passes added later with
+    vert: Shader,
+    /// Passes from the config, which come first
+    custom_count: usize,
+}
+
+/// Full-screen fragment shader, sampling the frame from `texImage`
+/// through the `Tex` coordinates. It also gets `size`, the frame size in
+/// pixels, and `time`, in seconds.
+pub struct Pass {
+    program: Program,
+    size: Uniform,
+    time: Uniform,
+    effect: Effect,
+}
+
+/// What a pass does, built-in ones having their own uniforms
+enum Effect {
+    /// Shader from the config
+    Custom,
+    /// Global colour filters, their matrix being set once
+    ColorFilter,
+    /// Multiplies colours by the white point of the night light
+    NightLight { white_point: Uniform },
+    /// Looks colours up in a 3D LUT, on texture unit 1
+    Lut { texture: Texture },
+}
+
+/// Most rects a LUT can apply to, as sized in `lut.frag`
+const MAX_REGIONS: usize = 16;
+
+impl Pass {
+    fn new(program: Program, effect: Effect) -> Pass {
+        Pass {
+            size: program.create_uniform("size"),
+            time: program.create_uniform("time"),
+            program,
+            effect,
+        }
+    }
+
+    /// Links one of the built-in fragment shaders
+    fn builtin(source: &str, vert: &Shader) -> Program {
+        let frag =
+            Shader::from_frag_source(&CString::new(source).unwrap()).unwrap();
+        Program::from_shaders(&[vert, &frag]).unwrap()
+    }
+}
+
+struct Target {
+    texture: Texture,
+    framebuffer: Framebuffer,
+}
+
+impl Target {
+    fn new(size: [i32; 2]) -> Target {
+        let texture = Texture::new();
+        texture.allocate(size[0], size[1]);
+        let framebuffer = Framebuffer::new(&texture);
+        Target { texture, framebuffer }
+    }
+}
+
+impl Pipeline {
+    /// Sets up the shader files of `config.opengl.post`, in order, then
+    /// the built-in effects that are enabled, ending with the colour
+    /// `corrections`. Shaders that fail to compile are reported and
+    /// skipped.
+    pub fn new(
+        size: [u16; 2],
+        config: &Config,
+        corrections: Vec<Correction>,
+    ) -> Pipeline {
+        let vert = Shader::from_vert_source(
+            &CString::new(include_str!("fullscreen.vert")).unwrap(),
+        )
+        .unwrap();
+        let mut passes = config
+            .opengl
+            .post
+            .iter()
+            .filter_map(|path| match custom::compile_file(path, &vert) {
+                Ok(program) => Some(Pass::new(program, Effect::Custom)),
+                Err(e) => {
+                    error!("Unable to compile post-processing pass:\n{}", e);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let custom_count = passes.len();
+        if config.night_light.enabled {
+            let program = Pass::builtin(include_str!("night.frag"), &vert);
+            let effect = Effect::NightLight {
+                white_point: program.create_uniform("whitePoint"),
+            };
+            passes.push(Pass::new(program, effect));
+        }
+        let quad = Buffer::new();
+        quad.bind();
+        quad.load_data(&[
+            -1.0f32, 1.0, 0.0, 1.0, // top left
+            1.0, 1.0, 1.0, 1.0, // top right
+            -1.0, -1.0, 0.0, 0.0, // bottom left
+            1.0, -1.0, 1.0, 0.0, // bottom right
+        ]);
+
+        let mut pipeline = Pipeline {
+            size: [size[0] as i32, size[1] as i32],
+            passes,
+            scene: None,
+            targets: Vec::new(),
+            quad,
+            start: Instant::now(),
+            night_light: config.night_light.clone(),
+            vert,
+            custom_count,
+        };
+        pipeline.set_corrections(corrections);
+        pipeline
+    }
+
+    /// Replaces the LUT passes, which come last, by ones for
+    /// `corrections`
+    pub fn set_corrections(&mut self, corrections: Vec<Correction>) {
+        self.passes.retain(|pass| !matches!(pass.effect, Effect::Lut { .. }));
+        let height = self.size[1];
+        for Correction { lut, rects } in corrections {
+            if rects.len() > MAX_REGIONS {
+                warn!("A LUT can only apply to {} outputs", MAX_REGIONS);
+            }
+            // Flipped for the origin at the bottom left
+            let regions: Vec<[f32; 4]> = rects
+                .iter()
+                .take(MAX_REGIONS)
+                .map(|&[x, y, width, rect_height]| {
+                    [x, height - y - rect_height, width, rect_height]
+                        .map(|v| v as f32)
+                })
+                .collect();
+            let program = Pass::builtin(include_str!("lut.frag"), &self.vert);
+            program.set_used();
+            program.create_uniform("lut").data_1i(1);
+            program.create_uniform("lutSize").data_1f(lut.size as f32);
+            program.create_uniform("domainMin").data_3f(&lut.domain_min);
+            program.create_uniform("domainMax").data_3f(&lut.domain_max);
+            program.create_uniform("regions").data_4fv(&regions);
+            program
+                .create_uniform("regionCount")
+                .data_1i(regions.len() as i32);
+            let texture = Texture::new();
+            texture.load_3d(lut.size as i32, &lut.table);
+            self.passes.push(Pass::new(program, Effect::Lut { texture }));
+        }
+        self.update_targets();
+    }
+
+    /// Sets the colour filters of the whole screen, applied right after
+    /// the passes from the config
+    pub fn set_color_matrix(&mut self, matrix: ColorMatrix) {
+        self.passes.retain(|pass| !matches!(pass.effect, Effect::ColorFilter));
+        if !matrix.is_identity() {
+            let program =
+                Pass::builtin(include_str!("filter.frag"), &self.vert);
+            program.set_used();
+            program
+                .create_uniform("colorMatrix")
+                .data_matrix_3f(&matrix.linear_part());
+            program.create_uniform("colorOffset").data_3f(&matrix.offset());
+            let pass = Pass::new(program, Effect::ColorFilter);
+            self.passes.insert(self.custom_count, pass);
+        }
+        self.update_targets();
+    }
+
+    /// Creates or drops the targets the passes need, which binds the
+    /// window framebuffer
+    fn update_targets(&mut self) {
+        let size = self.size;
+        if self.passes.is_empty() {
+            self.scene = None;
+        } else if self.scene.is_none() {
+            self.scene = Some(Target::new(size));
+        }
+        // Two are enough to alternate between
+        let count = self.passes.len().saturating_sub(1).min(2);
+        self.targets.resize_with(count, || Target::new(size));
+    }
+
+    /// Framebuffer frames get drawn into, `None` being the window
+    pub fn scene(&self) -> Option<&Framebuffer> {
+        self.scene.as_ref().map(|target| &target.framebuffer)
+    }
+
+    /// Binds the framebuffer frames get drawn into
+    pub fn bind_scene(&self) {
+        match self.scene() {
+            Some(framebuffer) => framebuffer.bind(),
+            None => Framebuffer::unbind(),
+        }
+    }
+
+    /// Runs the passes over the scene, the last one drawing to the window.
+    ///
+    /// Leaves the window framebuffer bound, blending enabled and one of
+    /// the passes in use.
+    pub fn run(&self) {
+        let scene = match &self.scene {
+            Some(scene) => scene,
+            None => return,
+        };
+        let [width, height] = self.size;
+        let time = self.start.elapsed().as_secs_f32();
+        self.quad.bind();
+        VertexArray::attrib_pointer(0, 2, 4, 0);
+        VertexArray::attrib_pointer(1, 2, 4, 2);
+        unsafe {
+            gl::Disable(gl::BLEND);
+            gl::Viewport(0, 0, width, height);
+        }
+        Texture::set_active(0);
+        for (i, pass) in self.passes.iter().enumerate() {
+            let source = match i {
+                0 => scene,
+                _ => &self.targets[(i - 1) % 2],
+            };
+            if i + 1 == self.passes.len() {
+                Framebuffer::unbind();
+            } else {
+                self.targets[i % 2].framebuffer.bind();
+            }
+            pass.program.set_used();
+            pass.size.data_2f(&[width as f32, height as f32]);
+            pass.time.data_1f(time);
+            match &pass.effect {
+                Effect::Custom | Effect::ColorFilter => (),
+                Effect::NightLight { white_point } => {
+                    if let Some(color) =
+                        night::current(&self.night_light, night::local_time())
+                    {
+                        white_point.data_3f(&color);
+                    }
+                }
+                Effect::Lut { texture } => {
+                    Texture::set_active(1);
+                    texture.bind_3d();
+                    Texture::set_active(0);
+                }
+            }
+            source.texture.bind();
+            unsafe {
+                gl::DrawElements(
+                    gl::TRIANGLES,
+                    6,
+                    gl::UNSIGNED_INT,
+                    std::ptr::null(),
+                );
+            }
+        }
+        unsafe { gl::Enable(gl::BLEND) };
+    }
+}
@@ -1,8 +1,9 @@
 extern crate gl;
 extern crate x11;
 
+use crate::config::Vsync;
 use std::ffi::{CStr, CString};
-use std::os::raw::{c_int, c_void};
+use std::os::raw::{c_int, c_uint, c_void};
 use std::ptr::null_mut;
 
 pub use x11::{glx::*, xlib};
@@ -10,6 +11,8 @@ pub use x11::{glx::*, xlib};
 // Minimum reuqired version for glxCreateContextAttribs extension
 const GLX_CONTEXT_MAJOR_VERSION_ARB: u32 = 0x2091;
 const GLX_CONTEXT_MINOR_VERSION_ARB: u32 = 0x2092;
+// GLX_EXT_buffer_age
+const GLX_BACK_BUFFER_AGE_EXT: c_int = 0x20F4;
 
 #[allow(non_upper_case_globals)]
 static mut ctx_error_occurred: bool = false;
@@ -42,6 +45,7 @@ pub fn verify_extensions(
 pub fn create_glx_context(
     conn: &xcb::Connection,
     fbc: GLXFBConfig,
+    version: [u8; 2],
 ) -> Result<GLXContext, &str> {
     // with glx, no need of a current context is needed to load symbols
     // otherwise we would need to create a temporary legacy GL context
@@ -56,9 +60,9 @@ pub fn create_glx_context(
 
     let context_attribs: [c_int; 5] = [
         GLX_CONTEXT_MAJOR_VERSION_ARB as c_int,
-        3,
+        version[0] as c_int,
         GLX_CONTEXT_MINOR_VERSION_ARB as c_int,
-        3,
+        version[1] as c_int,
         0,
     ];
     unsafe {
@@ -128,6 +132,119 @@ pub type GLXReleaseTexImageEXT = unsafe extern "C" fn(
     buffer: c_int,
 );
 
+// types for swap control extension functions
+pub type GLXSwapIntervalEXT = unsafe extern "C" fn(
+    dpy: *mut xlib::Display,
+    drawable: GLXDrawable,
+    interval: c_int,
+);
+pub type GLXSwapIntervalMESA = unsafe extern "C" fn(interval: c_uint) -> c_int;
+pub type GLXGetVideoSyncSGI =
+    unsafe extern "C" fn(count: *mut c_uint) -> c_int;
+pub type GLXWaitVideoSyncSGI = unsafe extern "C" fn(
+    divisor: c_int,
+    remainder: c_int,
+    count: *mut c_uint,
+) -> c_int;
+
+/// Waits for vertical blanks with GLX_SGI_video_sync
+pub struct VideoSync {
+    get: GLXGetVideoSyncSGI,
+    wait: GLXWaitVideoSyncSGI,
+}
+
+impl VideoSync {
+    fn load() -> VideoSync {
+        unsafe {
+            let get: GLXGetVideoSyncSGI =
+                std::mem::transmute(load_gl_func("glXGetVideoSyncSGI"));
+            let wait: GLXWaitVideoSyncSGI =
+                std::mem::transmute(load_gl_func("glXWaitVideoSyncSGI"));
+            VideoSync { get, wait }
+        }
+    }
+
+    /// Blocks until the next vertical blank
+    pub fn wait(&self) {
+        let mut count: c_uint = 0;
+        unsafe {
+            (self.get)(&mut count);
+            (self.wait)(2, ((count + 1) % 2) as c_int, &mut count);
+        }
+    }
+}
+
+/// Sets up `vsync` for `drawable`. With `Vsync::Sgi`, the returned
+/// `VideoSync` has to be waited for before every swap.
+pub fn set_vsync(
+    dpy: *mut xlib::Display,
+    screens: i32,
+    drawable: GLXDrawable,
+    vsync: Vsync,
+) -> Result<Option<VideoSync>, &'static str> {
+    let glx_exts = unsafe {
+        CStr::from_ptr(glXQueryExtensionsString(dpy, screens))
+            .to_str()
+            .unwrap()
+    };
+    let has_ext = check_glx_extension(glx_exts, "GLX_EXT_swap_control");
+    let has_mesa = check_glx_extension(glx_exts, "GLX_MESA_swap_control");
+    let has_sgi = check_glx_extension(glx_exts, "GLX_SGI_video_sync");
+    let method = match vsync {
+        Vsync::Auto if has_ext => Vsync::Ext,
+        Vsync::Auto if has_mesa => Vsync::Mesa,
+        Vsync::Auto if has_sgi => Vsync::Sgi,
+        Vsync::Auto => return Err("no swap control extension available"),
+        Vsync::Ext if !has_ext => return Err("no GLX_EXT_swap_control"),
+        Vsync::Mesa if !has_mesa => return Err("no GLX_MESA_swap_control"),
+        Vsync::Sgi if !has_sgi => return Err("no GLX_SGI_video_sync"),
+        method => method,
+    };
+    let interval = match method {
+        Vsync::Ext | Vsync::Mesa => 1,
+        // Swaps must not wait on top of the video sync
+        _ => 0,
+    };
+    if has_ext && method != Vsync::Mesa {
+        unsafe {
+            let swap_interval: GLXSwapIntervalEXT =
+                std::mem::transmute(load_gl_func("glXSwapIntervalEXT"));
+            swap_interval(dpy, drawable, interval);
+        }
+    } else if has_mesa {
+        unsafe {
+            let swap_interval: GLXSwapIntervalMESA =
+                std::mem::transmute(load_gl_func("glXSwapIntervalMESA"));
+            if swap_interval(interval as c_uint) != 0 {
+                return Err("glXSwapIntervalMESA failed");
+            }
+        }
+    } else if method == Vsync::Off {
+        return Err("no swap control extension available");
+    }
+    Ok((method == Vsync::Sgi).then(VideoSync::load))
+}
+
+/// Whether the age of back buffers can be queried with `buffer_age`
+pub fn has_buffer_age(dpy: *mut xlib::Display, screens: i32) -> bool {
+    let glx_exts = unsafe {
+        CStr::from_ptr(glXQueryExtensionsString(dpy, screens))
+            .to_str()
+            .unwrap()
+    };
+    check_glx_extension(glx_exts, "GLX_EXT_buffer_age")
+}
+
+/// Number of frames since the back buffer of `drawable` was drawn, 0
+/// when its contents are undefined
+pub fn buffer_age(dpy: *mut xlib::Display, drawable: GLXDrawable) -> u32 {
+    let mut age: c_uint = 0;
+    unsafe {
+        glXQueryDrawable(dpy, drawable, GLX_BACK_BUFFER_AGE_EXT, &mut age)
+    };
+    age
+}
+
 pub fn get_glxfbconfig(
     dpy: *mut xlib::Display,
     screens: i32,
@@ -173,6 +290,6 @@ pub unsafe extern "C" fn ctx_error_handler(
 pub unsafe fn check_gl_error() {
     let err = gl::GetError();
     if err != gl::NO_ERROR {
-        eprintln!("Got gl error: {}", err);
+        error!("Got gl error: {}", err);
     }
 }
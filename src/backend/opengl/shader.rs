@@ -57,6 +57,7 @@ fn shader_from_source(
                 null_mut(),
                 error.as_ptr() as *mut gl::types::GLchar,
             );
+            gl::DeleteShader(id);
         }
         return Err(error.to_string_lossy().into_owned());
     }
@@ -77,7 +78,7 @@ pub struct Program {
 }
 
 impl Program {
-    pub fn from_shaders(shaders: &[Shader]) -> Result<Program, String> {
+    pub fn from_shaders(shaders: &[&Shader]) -> Result<Program, String> {
         let program_id = unsafe { gl::CreateProgram() };
         for shader in shaders {
             unsafe { gl::AttachShader(program_id, shader.id) };
@@ -103,6 +104,7 @@ impl Program {
                     null_mut(),
                     error.as_ptr() as *mut gl::types::GLchar,
                 );
+                gl::DeleteProgram(program_id);
             }
             return Err(error.to_string_lossy().into_owned());
         }
@@ -149,9 +151,47 @@ pub struct Uniform {
 }
 
 impl Uniform {
+    pub fn data_1i(&self, data: i32) {
+        unsafe {
+            gl::Uniform1i(self.id, data);
+        }
+    }
+
+    pub fn data_1f(&self, data: f32) {
+        unsafe {
+            gl::Uniform1f(self.id, data);
+        }
+    }
+
     pub fn data_2f(&self, data: &[f32]) {
         unsafe {
             gl::Uniform2f(self.id, data[0], data[1]);
         }
     }
+
+    pub fn data_3f(&self, data: &[f32]) {
+        unsafe {
+            gl::Uniform3f(self.id, data[0], data[1], data[2]);
+        }
+    }
+
+    pub fn data_4f(&self, data: &[f32]) {
+        unsafe {
+            gl::Uniform4f(self.id, data[0], data[1], data[2], data[3]);
+        }
+    }
+
+    /// Sets a mat3 from its columns
+    pub fn data_matrix_3f(&self, data: &[f32; 9]) {
+        unsafe {
+            gl::UniformMatrix3fv(self.id, 1, gl::FALSE, data.as_ptr());
+        }
+    }
+
+    /// Sets an array of vec4
+    pub fn data_4fv(&self, data: &[[f32; 4]]) {
+        unsafe {
+            gl::Uniform4fv(self.id, data.len() as i32, data.as_ptr().cast());
+        }
+    }
 }
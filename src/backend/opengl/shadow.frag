@@ -0,0 +1,12 @@
+#version 330 core
+
+in vec2 Tex;
+
+uniform sampler2D mask;
+// Premultiplied shadow colour
+uniform vec4 color;
+
+void main()
+{
+    gl_FragColor = color * texture(mask, Tex).r;
+}
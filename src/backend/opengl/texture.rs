@@ -14,17 +14,69 @@ impl Texture {
     pub fn bind(&self) {
         unsafe { gl::BindTexture(gl::TEXTURE_2D, self.id) }
     }
-    pub fn set_active(i: u32) {
-        //TODO: proc macros
+    /// Allocates uninitialized RGBA storage, sampled linearly and clamped
+    /// to the edges
+    pub fn allocate(&self, width: i32, height: i32) {
+        self.bind();
+        unsafe {
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA8 as i32,
+                width,
+                height,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+            let params = [
+                (gl::TEXTURE_MIN_FILTER, gl::LINEAR),
+                (gl::TEXTURE_MAG_FILTER, gl::LINEAR),
+                (gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE),
+                (gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE),
+            ];
+            for (name, value) in params {
+                gl::TexParameteri(gl::TEXTURE_2D, name, value as i32);
+            }
+        }
+    }
+    /// Uploads a `size`³ table of RGB colours to a 3D texture, red
+    /// changing fastest, sampled linearly and clamped to the edges
+    pub fn load_3d(&self, size: i32, data: &[[f32; 3]]) {
+        self.bind_3d();
         unsafe {
-            match i {
-                0 => gl::ActiveTexture(gl::TEXTURE0),
-                1 => gl::ActiveTexture(gl::TEXTURE1),
-                2 => gl::ActiveTexture(gl::TEXTURE2),
-                3 => gl::ActiveTexture(gl::TEXTURE3),
-                _ => {}
+            gl::TexImage3D(
+                gl::TEXTURE_3D,
+                0,
+                gl::RGB16F as i32,
+                size,
+                size,
+                size,
+                0,
+                gl::RGB,
+                gl::FLOAT,
+                data.as_ptr().cast(),
+            );
+            let params = [
+                (gl::TEXTURE_MIN_FILTER, gl::LINEAR),
+                (gl::TEXTURE_MAG_FILTER, gl::LINEAR),
+                (gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE),
+                (gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE),
+                (gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE),
+            ];
+            for (name, value) in params {
+                gl::TexParameteri(gl::TEXTURE_3D, name, value as i32);
             }
-        };
+        }
+    }
+    pub fn bind_3d(&self) {
+        unsafe { gl::BindTexture(gl::TEXTURE_3D, self.id) }
+    }
+    /// Selects the texture unit `bind` applies to. Units are numbered
+    /// consecutively, at least 48 are available with OpenGL 3.3.
+    pub fn set_active(i: u32) {
+        unsafe { gl::ActiveTexture(gl::TEXTURE0 + i) };
     }
     pub fn unbind() {
         unsafe { gl::BindTexture(gl::TEXTURE_2D, 0) }
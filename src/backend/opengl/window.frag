@@ -3,8 +3,40 @@
 in vec2 Tex;
 
 uniform sampler2D texImage;
+uniform float opacity;
+uniform float dim;
+// Colour filters, applied to premultiplied colours as
+// colorMatrix * rgb + colorOffset * alpha
+uniform mat3 colorMatrix;
+uniform vec3 colorOffset;
+// Window size in pixels
+uniform vec2 size;
+// Corner radius in pixels, 0 for square corners
+uniform float radius;
+// Border along the edge, 0 for none
+uniform float borderWidth;
+uniform vec3 borderColor;
+
+// Signed distance to the edge of the rounded window, negative inside
+float edgeDistance(vec2 pos)
+{
+    vec2 q = abs(pos - size / 2.0) - (size / 2.0 - radius);
+    return length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - radius;
+}
 
 void main()
 {
-    gl_FragColor = texture(texImage, Tex);
+    vec4 color = texture(texImage, Tex);
+    color.rgb = clamp(colorMatrix * color.rgb + colorOffset * color.a,
+        0.0, color.a);
+    color.rgb *= 1.0 - dim;
+    float dist = edgeDistance(Tex * size);
+    if (borderWidth > 0.0) {
+        float border = clamp(dist + borderWidth + 0.5, 0.0, 1.0);
+        color = mix(color, vec4(borderColor, 1.0), border);
+    }
+    // Edges are smoothed over a pixel
+    color *= clamp(0.5 - dist, 0.0, 1.0);
+    // Colours are premultiplied, so all channels get scaled
+    gl_FragColor = color * opacity;
 }
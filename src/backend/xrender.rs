@@ -0,0 +1,893 @@
+use super::Backend;
+use crate::config::{Config, CornerConfig, NightLightConfig, ShadowConfig};
+use crate::corners::Corners;
+use crate::night;
+use crate::shadow::Mask;
+use crate::state::State;
+use crate::window::Window;
+use std::cell::Cell;
+use xcb::render;
+
+/// Fallback backend using the XRender extension.
+///
+/// Slower than the OpenGL one, but works without GLX support
+/// (VMs, thin clients, etc.)
+pub struct XRender<'a> {
+    pub conn: &'a xcb::Connection,
+    /// Picture of the composite overlay window
+    pub target: render::Picture,
+    /// Offscreen picture every window gets drawn on before being
+    /// copied to `target`, prevents flickering
+    pub buffer: render::Picture,
+    pub root_picture: render::Picture,
+    buffer_pixmap: xcb::Pixmap,
+    /// Second picture of the buffer, reading it through a horizontal
+    /// blur filter
+    blur_source: render::Picture,
+    blur_strength: u8,
+    /// Format and depth of the buffer
+    format: render::Pictformat,
+    depth: u8,
+    width: u16,
+    height: u16,
+    /// Darkening of unfocused windows, from the config
+    inactive_dim: f32,
+    shadow: ShadowConfig,
+    corners: CornerConfig,
+    night_light: NightLightConfig,
+    /// Cached (visual, pictformat) pairs of the server
+    visual_formats: Vec<(xcb::Visualid, render::Pictformat)>,
+    /// 8 bit alpha-only format, used for shadow masks
+    a8_format: render::Pictformat,
+    /// Drawable new pixmaps are created for
+    root: xcb::Window,
+    /// Whether the missing support for colour filters of windows was
+    /// reported already, which happens once
+    warned_filters: Cell<bool>,
+}
+
+impl<'a> XRender<'a> {
+    pub fn init<'b>(
+        state: &'b State,
+        config: &Config,
+    ) -> Result<XRender<'b>, &'b str> {
+        let conn = &state.conn;
+        if !conn.get_extension_data(render::id()).unwrap().present() {
+            return Err("render extension not found");
+        }
+        let setup = conn.get_setup();
+        let screen = setup.roots().last().unwrap();
+
+        let (visual_formats, a8_format) =
+            match render::query_pict_formats(conn).get_reply() {
+                Ok(reply) => {
+                    let mut formats = Vec::new();
+                    for screen in reply.screens() {
+                        for depth in screen.depths() {
+                            for visual in depth.visuals() {
+                                formats
+                                    .push((visual.visual(), visual.format()));
+                            }
+                        }
+                    }
+                    let a8 = reply.formats().find(|f| {
+                        f.depth() == 8
+                            && f.type_() == render::PICT_TYPE_DIRECT as u8
+                            && f.direct().alpha_mask() == 0xff
+                            && f.direct().red_mask() == 0
+                    });
+                    match a8 {
+                        Some(format) => (formats, format.id()),
+                        None => return Err("no A8 picture format"),
+                    }
+                }
+                Err(_) => return Err("unable to query picture formats"),
+            };
+        let root_format =
+            match find_format(&visual_formats, screen.root_visual()) {
+                Some(format) => format,
+                None => return Err("no picture format for root visual"),
+            };
+        let (width, height) = (state.root.width, state.root.height);
+
+        let target = conn.generate_id();
+        render::create_picture(conn, target, state.overlay, root_format, &[])
+            .request_check()
+            .map_err(|_| "unable to create overlay picture")?;
+
+        let buffer_pixmap = conn.generate_id();
+        xcb::create_pixmap(
+            conn,
+            screen.root_depth(),
+            buffer_pixmap,
+            state.overlay,
+            width,
+            height,
+        );
+        let buffer = conn.generate_id();
+        render::create_picture(conn, buffer, buffer_pixmap, root_format, &[])
+            .request_check()
+            .map_err(|_| "unable to create buffer picture")?;
+
+        let blur_source = conn.generate_id();
+        render::create_picture(
+            conn,
+            blur_source,
+            buffer_pixmap,
+            root_format,
+            &[],
+        )
+        .request_check()
+        .map_err(|_| "unable to create blur picture")?;
+        set_blur_filter(conn, blur_source, config.blur.strength, true);
+
+        let root_picture = if state.root.pixmap != xcb::NONE {
+            let picture = conn.generate_id();
+            render::create_picture(
+                conn,
+                picture,
+                state.root.pixmap,
+                root_format,
+                &[],
+            )
+            .request_check()
+            .map_err(|_| "unable to create root picture")?;
+            picture
+        } else {
+            xcb::NONE
+        };
+        warn_lut(config);
+
+        let xrender = XRender {
+            conn,
+            target,
+            buffer,
+            root_picture,
+            buffer_pixmap,
+            blur_source,
+            blur_strength: config.blur.strength,
+            format: root_format,
+            depth: screen.root_depth(),
+            width,
+            height,
+            inactive_dim: config.inactive_dim,
+            shadow: config.shadow.clone(),
+            corners: config.corners.clone(),
+            night_light: config.night_light.clone(),
+            visual_formats,
+            a8_format,
+            root: state.root.id,
+            warned_filters: Cell::new(false),
+        };
+        // `render` draws the root after presenting, so the first frame
+        // needs it drawn beforehand
+        xrender.draw_root();
+        Ok(xrender)
+    }
+
+    /// Blurs what is drawn behind `win`, in two passes through a
+    /// temporary picture
+    fn blur_behind(&self, win: &Window<BackendContext>) {
+        let (x, y, width, height) = pixel_rect(win.draw_rect(
+            0.0,
+            0.0,
+            win.outer_width() as f32,
+            win.outer_height() as f32,
+        ));
+        // Keeps the edges from blurring with transparent pixels
+        let pad = blur_radius(self.blur_strength) as i16;
+        let width = width + pad as u16 * 2;
+        let height = height + pad as u16 * 2;
+        let pixmap = self.conn.generate_id();
+        let picture = self.conn.generate_id();
+        xcb::create_pixmap(
+            self.conn, self.depth, pixmap, self.root, width, height,
+        );
+        render::create_picture(self.conn, picture, pixmap, self.format, &[]);
+        xcb::free_pixmap(self.conn, pixmap);
+        set_blur_filter(self.conn, picture, self.blur_strength, false);
+
+        render::composite(
+            self.conn,
+            render::PICT_OP_SRC as u8,
+            self.blur_source,
+            xcb::NONE,
+            picture,
+            x - pad,
+            y - pad,
+            0,
+            0,
+            0,
+            0,
+            width,
+            height,
+        );
+        for rect in win.blur_rects() {
+            let (rect_x, rect_y, rect_width, rect_height) =
+                pixel_rect(win.draw_rect(
+                    rect.x() as f32,
+                    rect.y() as f32,
+                    rect.width() as f32,
+                    rect.height() as f32,
+                ));
+            // The blurred picture is opaque, so OVER only lets through
+            // what is outside rounded corners
+            render::composite(
+                self.conn,
+                render::PICT_OP_OVER as u8,
+                picture,
+                win.context.corners,
+                self.buffer,
+                rect_x - x + pad,
+                rect_y - y + pad,
+                rect_x - x,
+                rect_y - y,
+                rect_x,
+                rect_y,
+                rect_width,
+                rect_height,
+            );
+        }
+        render::free_picture(self.conn, picture);
+    }
+
+    /// Draws the shadow colour through the shadow mask of `win`
+    fn draw_shadow(&self, win: &Window<BackendContext>, opacity: f32) {
+        let radius = self.shadow.radius as i16;
+        let [dx, dy] = self.shadow.offset;
+        let alpha = self.shadow.opacity * opacity;
+        // Solid fill colours are premultiplied
+        let [r, g, b] = self
+            .shadow
+            .color
+            .to_f32()
+            .map(|c| (c * alpha * 0xffff as f32) as u16);
+        let color = self.conn.generate_id();
+        render::create_solid_fill(
+            self.conn,
+            color,
+            render::Color::new(r, g, b, (alpha * 0xffff as f32) as u16),
+        );
+        let (x, y, width, height) = pixel_rect(win.draw_rect(
+            (dx - radius) as f32,
+            (dy - radius) as f32,
+            (win.outer_width() + radius as u16 * 2) as f32,
+            (win.outer_height() + radius as u16 * 2) as f32,
+        ));
+        let scale = win.transform().scale;
+        // Masks built before a resize are stretched until it settles
+        let [mask_width, mask_height] = win.context.mask_size;
+        let stretch = |size: u16, mask: u16| {
+            scale * (size as f32 + radius as f32 * 2.0)
+                / (mask as f32 + radius as f32 * 2.0)
+        };
+        let shadow_scale = [
+            stretch(win.outer_width(), mask_width),
+            stretch(win.outer_height(), mask_height),
+        ];
+        if shadow_scale != [1.0, 1.0] {
+            set_scale(self.conn, win.context.shadow, shadow_scale);
+        }
+        render::composite(
+            self.conn,
+            render::PICT_OP_OVER as u8,
+            color,
+            win.context.shadow,
+            self.buffer,
+            0,
+            0,
+            0,
+            0,
+            x,
+            y,
+            width,
+            height,
+        );
+        if shadow_scale != [1.0, 1.0] {
+            set_scale(self.conn, win.context.shadow, [1.0, 1.0]);
+        }
+        render::free_picture(self.conn, color);
+    }
+
+    /// Uploads one byte of alpha per pixel to a new A8 picture
+    fn alpha_picture(
+        &self,
+        width: usize,
+        height: usize,
+        alpha: &[u8],
+    ) -> Option<render::Picture> {
+        // ZPixmap rows are padded to 32 bits
+        let stride = (width + 3) & !3;
+        let mut data = vec![0u8; stride * height];
+        for (src, dst) in alpha.chunks(width).zip(data.chunks_mut(stride)) {
+            dst[..width].copy_from_slice(src);
+        }
+
+        let pixmap = self.conn.generate_id();
+        let gc = self.conn.generate_id();
+        let picture = self.conn.generate_id();
+        let (width, height) = (width as u16, height as u16);
+        xcb::create_pixmap(self.conn, 8, pixmap, self.root, width, height);
+        xcb::create_gc(self.conn, gc, pixmap, &[]);
+        xcb::put_image(
+            self.conn,
+            xcb::IMAGE_FORMAT_Z_PIXMAP as u8,
+            pixmap,
+            gc,
+            width,
+            height,
+            0,
+            0,
+            0,
+            8,
+            &data,
+        );
+        xcb::free_gc(self.conn, gc);
+        let created = render::create_picture(
+            self.conn,
+            picture,
+            pixmap,
+            self.a8_format,
+            &[],
+        )
+        .request_check();
+        // The picture keeps the pixmap alive
+        xcb::free_pixmap(self.conn, pixmap);
+        created.ok().map(|_| picture)
+    }
+
+    /// A8 picture of the size of `win` kept in `cache`, created again
+    /// when the size changed
+    fn scratch(
+        &self,
+        cache: &Cell<Scratch>,
+        win: &Window<BackendContext>,
+    ) -> Scratch {
+        let size = [win.outer_width(), win.outer_height()];
+        let scratch = cache.get();
+        if scratch.picture != xcb::NONE && scratch.size == size {
+            return scratch;
+        }
+        if scratch.picture != xcb::NONE {
+            render::free_picture(self.conn, scratch.picture);
+        }
+        let pixmap = self.conn.generate_id();
+        let picture = self.conn.generate_id();
+        xcb::create_pixmap(self.conn, 8, pixmap, self.root, size[0], size[1]);
+        render::create_picture(
+            self.conn,
+            picture,
+            pixmap,
+            self.a8_format,
+            &[],
+        );
+        xcb::free_pixmap(self.conn, pixmap);
+        let scratch = Scratch { picture, size, opacity: None };
+        cache.set(scratch);
+        scratch
+    }
+
+    /// Corners of `win` masked by `opacity`, only redrawn when the size
+    /// or the opacity changes
+    fn faded(
+        &self,
+        win: &Window<BackendContext>,
+        opacity: f32,
+    ) -> render::Picture {
+        let mut faded = self.scratch(&win.context.faded, win);
+        if faded.opacity != Some(opacity) {
+            let fade = self.conn.generate_id();
+            let alpha = (opacity * 0xffff as f32) as u16;
+            render::create_solid_fill(
+                self.conn,
+                fade,
+                render::Color::new(0, 0, 0, alpha),
+            );
+            self.fill_scratch(faded, win.context.corners, fade);
+            render::free_picture(self.conn, fade);
+            faded.opacity = Some(opacity);
+            win.context.faded.set(faded);
+        }
+        faded.picture
+    }
+
+    /// Alpha of `win` masked by its corners, redrawn every time since
+    /// the contents change
+    fn coverage(&self, win: &Window<BackendContext>) -> render::Picture {
+        let coverage = self.scratch(&win.context.coverage, win);
+        self.fill_scratch(coverage, win.context.picture, win.context.corners);
+        coverage.picture
+    }
+
+    /// Replaces the contents of `scratch` by `source` through `mask`
+    fn fill_scratch(
+        &self,
+        scratch: Scratch,
+        source: render::Picture,
+        mask: render::Picture,
+    ) {
+        let [width, height] = scratch.size;
+        render::composite(
+            self.conn,
+            render::PICT_OP_SRC as u8,
+            source,
+            mask,
+            scratch.picture,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            width,
+            height,
+        );
+    }
+
+    fn draw_root(&self) {
+        if self.root_picture == xcb::NONE {
+            render::fill_rectangles(
+                self.conn,
+                render::PICT_OP_SRC as u8,
+                self.buffer,
+                render::Color::new(0, 0, 0, 0xffff),
+                &[xcb::Rectangle::new(0, 0, self.width, self.height)],
+            );
+            return;
+        }
+        render::composite(
+            self.conn,
+            render::PICT_OP_SRC as u8,
+            self.root_picture,
+            xcb::NONE,
+            self.buffer,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            self.width,
+            self.height,
+        );
+    }
+}
+
+impl<'a> Backend for XRender<'a> {
+    type Context = BackendContext;
+
+    fn init_window(&self, _win: &mut Window<BackendContext>) {
+        // Pictures are created along with the pixmap
+    }
+
+    fn update_pixmap(&self, win: &mut Window<BackendContext>) {
+        if win.context.picture != xcb::NONE {
+            render::free_picture(self.conn, win.context.picture);
+            win.context.picture = xcb::NONE;
+        }
+        // The window can be destroyed before the event gets handled, it is
+        // then left out until it is gone
+        if let Err(e) = win.update_pixmap(self.conn) {
+            warn!("Unable to get the pixmap of window {}: {}", win.id, e);
+            return;
+        }
+        let format = match find_format(&self.visual_formats, win.visual) {
+            Some(format) => format,
+            None => {
+                warn!("No picture format for window: {}", win.id);
+                return;
+            }
+        };
+        let picture = self.conn.generate_id();
+        match render::create_picture(
+            self.conn,
+            picture,
+            win.pixmap,
+            format,
+            &[(
+                render::CP_SUBWINDOW_MODE,
+                xcb::SUBWINDOW_MODE_INCLUDE_INFERIORS,
+            )],
+        )
+        .request_check()
+        {
+            Ok(_) => win.context.picture = picture,
+            Err(_) => warn!("Unable to create picture for: {}", win.id),
+        }
+    }
+
+    fn update_pos(&self, _win: &Window<BackendContext>) {
+        // Position is only used while drawing
+    }
+
+    fn update_texture(&self, _win: &mut Window<BackendContext>) {
+        // The picture always reflects the current pixmap contents
+    }
+
+    fn update_masks(&self, win: &mut Window<BackendContext>) {
+        let ctx = &mut win.context;
+        for picture in [&mut ctx.shadow, &mut ctx.corners, &mut ctx.border] {
+            if *picture != xcb::NONE {
+                render::free_picture(self.conn, *picture);
+                *picture = xcb::NONE;
+            }
+        }
+        // Both are made from the corners
+        for cache in [&ctx.faded, &ctx.coverage] {
+            let scratch = cache.take();
+            if scratch.picture != xcb::NONE {
+                render::free_picture(self.conn, scratch.picture);
+            }
+        }
+        win.context.mask_size = [win.outer_width(), win.outer_height()];
+        let corner_radius = win.corner_radius(self.corners.radius);
+        if win.has_shadow(self.shadow.enabled) {
+            let mask = Mask::build(win, self.shadow.radius, corner_radius);
+            match self.alpha_picture(mask.width, mask.height, &mask.data) {
+                Some(picture) => win.context.shadow = picture,
+                None => warn!("Unable to create shadow for: {}", win.id),
+            }
+        }
+        let border_width = self.corners.border_width;
+        if win.rounded() && (corner_radius > 0 || border_width > 0) {
+            let corners = Corners::build(
+                win.outer_width() as usize,
+                win.outer_height() as usize,
+                corner_radius as f32,
+                border_width as f32,
+            );
+            let (width, height) = (corners.width, corners.height);
+            match self.alpha_picture(width, height, &corners.inside) {
+                Some(picture) => win.context.corners = picture,
+                None => warn!("Unable to create corners for: {}", win.id),
+            }
+            if !corners.border.is_empty() {
+                match self.alpha_picture(width, height, &corners.border) {
+                    Some(picture) => win.context.border = picture,
+                    None => warn!("Unable to create border for: {}", win.id),
+                }
+            }
+        }
+    }
+
+    fn draw_window(&self, win: &Window<BackendContext>) {
+        let ctx = &win.context;
+        let scale = win.transform().scale;
+        // Zooming windows start from nothing
+        if ctx.picture == xcb::NONE || scale <= 0.0 {
+            return;
+        }
+        if !win.color_matrix().is_identity() && !self.warned_filters.get() {
+            warn!("The xrender backend ignores colour filters of windows");
+            self.warned_filters.set(true);
+        }
+        let opacity = win.draw_opacity();
+        if ctx.shadow != xcb::NONE {
+            self.draw_shadow(win, opacity);
+        }
+        let rounded = ctx.corners != xcb::NONE;
+        // Masks built before a resize are stretched until it settles
+        let [mask_width, mask_height] = ctx.mask_size;
+        let stretch = [
+            win.outer_width() as f32 / mask_width.max(1) as f32,
+            win.outer_height() as f32 / mask_height.max(1) as f32,
+        ];
+        let stretched = [ctx.corners, ctx.border]
+            .into_iter()
+            .filter(|p| *p != xcb::NONE)
+            .collect::<Vec<_>>();
+        if stretch != [1.0, 1.0] {
+            for picture in &stretched {
+                set_scale(self.conn, *picture, stretch);
+            }
+        }
+        let op = if win.alpha || opacity < 1.0 || rounded {
+            render::PICT_OP_OVER
+        } else {
+            render::PICT_OP_SRC
+        };
+        // Translucency is applied through an alpha mask, solid unless
+        // the corners are rounded
+        let mut owned_mask = false;
+        let mask = if opacity < 1.0 && rounded {
+            self.faded(win, opacity)
+        } else if opacity < 1.0 {
+            let mask = self.conn.generate_id();
+            let alpha = (opacity * 0xffff as f32) as u16;
+            render::create_solid_fill(
+                self.conn,
+                mask,
+                render::Color::new(0, 0, 0, alpha),
+            );
+            owned_mask = true;
+            mask
+        } else if rounded {
+            ctx.corners
+        } else {
+            xcb::NONE
+        };
+        let dimmed = win.dimmed() && self.inactive_dim > 0.0;
+        // The shade covers the window where it is opaque, which is inside
+        // its corners for rounded windows
+        let shade_mask = match (dimmed, rounded, win.alpha) {
+            (false, _, _) => xcb::NONE,
+            (true, true, true) => self.coverage(win),
+            (true, true, false) => ctx.corners,
+            (true, false, _) => ctx.picture,
+        };
+        // Pictures are only transformed while animating
+        let mut scaled = vec![ctx.picture];
+        for picture in [mask, shade_mask] {
+            let scratch = picture != ctx.corners && picture != ctx.picture;
+            if rounded && scratch && picture != xcb::NONE {
+                scaled.push(picture);
+            }
+        }
+        if scale != 1.0 {
+            for picture in &scaled {
+                set_scale(self.conn, *picture, [scale, scale]);
+            }
+        }
+        if scale != 1.0 || stretch != [1.0, 1.0] {
+            let [x, y] = stretch.map(|s| s * scale);
+            for picture in &stretched {
+                set_scale(self.conn, *picture, [x, y]);
+            }
+        }
+        if win.has_blur() {
+            self.blur_behind(win);
+        }
+        let (x, y, width, height) = pixel_rect(win.draw_rect(
+            0.0,
+            0.0,
+            win.outer_width() as f32,
+            win.outer_height() as f32,
+        ));
+        render::composite(
+            self.conn,
+            op as u8,
+            ctx.picture,
+            mask,
+            self.buffer,
+            0,
+            0,
+            0,
+            0,
+            x,
+            y,
+            width,
+            height,
+        );
+        if owned_mask {
+            render::free_picture(self.conn, mask);
+        }
+        if dimmed {
+            // Black drawn over the window
+            let shade = self.conn.generate_id();
+            let alpha = (self.inactive_dim * opacity * 0xffff as f32) as u16;
+            render::create_solid_fill(
+                self.conn,
+                shade,
+                render::Color::new(0, 0, 0, alpha),
+            );
+            render::composite(
+                self.conn,
+                render::PICT_OP_OVER as u8,
+                shade,
+                shade_mask,
+                self.buffer,
+                0,
+                0,
+                0,
+                0,
+                x,
+                y,
+                width,
+                height,
+            );
+            render::free_picture(self.conn, shade);
+        }
+        if ctx.border != xcb::NONE {
+            let color = self.conn.generate_id();
+            // Solid fill colours are premultiplied
+            let [r, g, b] = self
+                .corners
+                .border_color
+                .to_f32()
+                .map(|c| (c * opacity * 0xffff as f32) as u16);
+            let alpha = (opacity * 0xffff as f32) as u16;
+            render::create_solid_fill(
+                self.conn,
+                color,
+                render::Color::new(r, g, b, alpha),
+            );
+            render::composite(
+                self.conn,
+                render::PICT_OP_OVER as u8,
+                color,
+                ctx.border,
+                self.buffer,
+                0,
+                0,
+                0,
+                0,
+                x,
+                y,
+                width,
+                height,
+            );
+            render::free_picture(self.conn, color);
+        }
+        for picture in scaled.iter().chain(&stretched) {
+            set_scale(self.conn, *picture, [1.0, 1.0]);
+        }
+    }
+
+    fn render(&self) {
+        if let Some(white_point) =
+            night::current(&self.night_light, night::local_time())
+        {
+            let [red, green, blue] =
+                white_point.map(|c| (c * 0xffff as f32) as u16);
+            // Multiplies the colours of the frame, which is opaque
+            render::fill_rectangles(
+                self.conn,
+                render::PICT_OP_MULTIPLY as u8,
+                self.buffer,
+                render::Color::new(red, green, blue, 0xffff),
+                &[xcb::Rectangle::new(0, 0, self.width, self.height)],
+            );
+        }
+        render::composite(
+            self.conn,
+            render::PICT_OP_SRC as u8,
+            self.buffer,
+            xcb::NONE,
+            self.target,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            self.width,
+            self.height,
+        );
+        // Start the next frame
+        self.draw_root();
+        self.conn.flush();
+    }
+
+    fn reconfigure(&mut self, config: &Config) {
+        self.inactive_dim = config.inactive_dim;
+        self.shadow = config.shadow.clone();
+        self.corners = config.corners.clone();
+        self.night_light = config.night_light.clone();
+        warn_lut(config);
+        if config.blur.strength != self.blur_strength {
+            self.blur_strength = config.blur.strength;
+            set_blur_filter(
+                self.conn,
+                self.blur_source,
+                self.blur_strength,
+                true,
+            );
+        }
+    }
+}
+
+/// 3D lookups are beyond what XRender can do, frames are drawn without
+/// the LUTs. They are still accepted by the config since the OpenGL
+/// backend falls back to this one.
+fn warn_lut(config: &Config) {
+    if config.lut.file.is_some() || !config.lut.outputs.is_empty() {
+        warn!("LUTs are not supported by the xrender backend");
+    }
+}
+
+impl<'a> Drop for XRender<'a> {
+    fn drop(&mut self) {
+        render::free_picture(self.conn, self.target);
+        render::free_picture(self.conn, self.buffer);
+        render::free_picture(self.conn, self.blur_source);
+        if self.root_picture != xcb::NONE {
+            render::free_picture(self.conn, self.root_picture);
+        }
+        xcb::free_pixmap(self.conn, self.buffer_pixmap);
+        self.conn.flush();
+    }
+}
+
+#[derive(Default)]
+pub struct BackendContext {
+    pub picture: render::Picture,
+    /// Shadow mask, `NONE` for windows without shadow
+    pub shadow: render::Picture,
+    /// Coverage of the window with its rounded corners, and of its
+    /// border, `NONE` for square windows and windows without a border
+    pub corners: render::Picture,
+    pub border: render::Picture,
+    /// Outer size of the window the masks were built for
+    pub mask_size: [u16; 2],
+    /// Corners masked by the opacity, for translucent rounded windows
+    pub faded: Cell<Scratch>,
+    /// Alpha masked by the corners, for dimmed rounded ARGB windows
+    pub coverage: Cell<Scratch>,
+}
+
+/// A8 picture of the outer size of a window, kept between frames
+#[derive(Clone, Copy, Default)]
+pub struct Scratch {
+    picture: render::Picture,
+    size: [u16; 2],
+    /// Opacity the corners were masked by, when holding them
+    opacity: Option<f32>,
+}
+
+fn find_format(
+    formats: &[(xcb::Visualid, render::Pictformat)],
+    visual: xcb::Visualid,
+) -> Option<render::Pictformat> {
+    formats.iter().find(|(v, _)| *v == visual).map(|(_, format)| *format)
+}
+
+/// Rounds a rectangle from `Window::draw_rect` to pixels
+fn pixel_rect([x, y, width, height]: [f32; 4]) -> (i16, i16, u16, u16) {
+    (
+        x.round() as i16,
+        y.round() as i16,
+        width.round() as u16,
+        height.round() as u16,
+    )
+}
+
+/// Scales `picture` around its origin when it gets composited, by
+/// [horizontal, vertical] factors
+fn set_scale(
+    conn: &xcb::Connection,
+    picture: render::Picture,
+    scale: [f32; 2],
+) {
+    // The transform maps destination pixels to source ones, in 16.16
+    // fixed point
+    let [x, y] = scale.map(|s| (65536.0 / s) as render::Fixed);
+    render::set_picture_transform(
+        conn,
+        picture,
+        render::Transform::new(x, 0, 0, 0, y, 0, 0, 0, 1 << 16),
+    );
+    let filter = if scale == [1.0, 1.0] { "nearest" } else { "bilinear" };
+    render::set_picture_filter(conn, picture, filter, &[]);
+}
+
+/// Radius of the gaussian kernel used for a blur strength
+fn blur_radius(strength: u8) -> usize {
+    strength as usize * 2
+}
+
+/// Sets a one dimensional gaussian convolution filter on `picture`
+fn set_blur_filter(
+    conn: &xcb::Connection,
+    picture: render::Picture,
+    strength: u8,
+    horizontal: bool,
+) {
+    let radius = blur_radius(strength);
+    let sigma = radius as f32 / 2.0;
+    let weights: Vec<f32> = (0..radius * 2 + 1)
+        .map(|i| {
+            let d = i as f32 - radius as f32;
+            (-d * d / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let total: f32 = weights.iter().sum();
+    let size = weights.len() as f32;
+    let (width, height) = if horizontal { (size, 1.0) } else { (1.0, size) };
+    // Values are 16.16 fixed point: width, height, then the kernel
+    let values: Vec<render::Fixed> = [width, height]
+        .into_iter()
+        .chain(weights.iter().map(|w| w / total))
+        .map(|v| (v * 65536.0) as render::Fixed)
+        .collect();
+    render::set_picture_filter(conn, picture, "convolution", &values);
+}
@@ -0,0 +1,138 @@
+use crate::config::{BackendKind, Config, Vsync};
+use crate::log::Level;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: rcomp [OPTIONS]
+
+Options:
+  --display <name>      X display to connect to, defaults to $DISPLAY
+  --config <path>       Config file to use instead of the default one
+  --backend <name>      Rendering backend: opengl, xrender or headless
+  --replace             Replace an already running compositor
+  --vsync <mode>        Synchronize frames with the display refresh:
+                        on, off, ext, mesa or sgi
+  --log-level <level>   Verbosity: error, warn, info or debug
+  --fps-cap <fps>       Maximum number of frames drawn per second
+  --print-config        Print the effective configuration and exit
+  --diagnose            Print information about the X server and exit
+  --version             Print the version and exit
+  -h, --help            Print this message and exit";
+
+/// Options given on the command line.
+///
+/// Every option that also exists in the config file overrides it.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub display: Option<String>,
+    pub config: Option<PathBuf>,
+    pub backend: Option<BackendKind>,
+    pub replace: bool,
+    pub vsync: Option<Vsync>,
+    pub log_level: Option<Level>,
+    pub fps_cap: Option<u64>,
+    pub print_config: bool,
+    pub diagnose: bool,
+    pub version: bool,
+    pub help: bool,
+}
+
+impl Options {
+    /// Parses the arguments, excluding the program name
+    pub fn parse<I>(args: I) -> Result<Options, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // Accept both `--opt value` and `--opt=value`
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => {
+                    (name.to_string(), Some(value.to_string()))
+                }
+                _ => (arg, None),
+            };
+            if inline_value.is_some() && !takes_value(&name) {
+                return Err(format!(
+                    "option `{}` does not take a value",
+                    name
+                ));
+            }
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or(format!("option `{}` requires a value", name))
+            };
+            match name.as_str() {
+                "--display" => options.display = Some(value()?),
+                "--config" => options.config = Some(value()?.into()),
+                "--backend" => {
+                    options.backend = Some(parse_backend(&value()?)?)
+                }
+                "--vsync" => options.vsync = Some(value()?.parse()?),
+                "--log-level" => options.log_level = Some(value()?.parse()?),
+                "--fps-cap" => options.fps_cap = Some(parse_fps(&value()?)?),
+                "--replace" => options.replace = true,
+                "--print-config" => options.print_config = true,
+                "--diagnose" => options.diagnose = true,
+                "--version" => options.version = true,
+                "-h" | "--help" => options.help = true,
+                _ => return Err(format!("unknown option `{}`", name)),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Overrides the config values set on the command line
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(backend) = self.backend {
+            config.backend = backend;
+        }
+        if let Some(vsync) = self.vsync {
+            config.vsync = vsync;
+        }
+        if let Some(level) = self.log_level {
+            config.log_level = level;
+        }
+        if let Some(fps) = self.fps_cap {
+            // Round up, so the cap is never exceeded
+            config.frame_time = Some(1000_u64.div_ceil(fps));
+        }
+    }
+}
+
+fn takes_value(name: &str) -> bool {
+    matches!(
+        name,
+        "--display"
+            | "--config"
+            | "--backend"
+            | "--vsync"
+            | "--log-level"
+            | "--fps-cap"
+    )
+}
+
+fn parse_backend(value: &str) -> Result<BackendKind, String> {
+    match value {
+        "opengl" => Ok(BackendKind::Opengl),
+        "xrender" => Ok(BackendKind::Xrender),
+        "headless" => Ok(BackendKind::Headless),
+        _ => Err(format!(
+            "invalid backend `{}`, expected one of: opengl, xrender, headless",
+            value
+        )),
+    }
+}
+
+fn parse_fps(value: &str) -> Result<u64, String> {
+    match value.parse::<u64>() {
+        Ok(fps) if (1..=1000).contains(&fps) => Ok(fps),
+        _ => Err(format!(
+            "invalid frame rate `{}`, expected a number between 1 and 1000",
+            value
+        )),
+    }
+}
@@ -0,0 +1,770 @@
+use crate::anim::{Curve, Kind};
+use crate::filter::ColorFilter;
+use crate::log::Level;
+use crate::night::TimeOfDay;
+use crate::rules::Rule;
+use regex::Regex;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Compositor configuration, loaded from `rcomp.toml`.
+///
+/// Every field has a default, so an empty (or missing) file is a valid
+/// configuration. See `rcomp.sample.toml` for a documented example.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Rendering backend
+    pub backend: BackendKind,
+    /// Minimum time between two frames, in milliseconds.
+    /// Taken from the refresh rate of the fastest output when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_time: Option<u64>,
+    /// Synchronize buffer swaps with the display refresh
+    pub vsync: Vsync,
+    /// Verbosity of the messages printed to stderr
+    pub log_level: Level,
+    /// Size of the area windows are drawn on, in pixels.
+    /// Taken from the root window when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screen_size: Option<[u16; 2]>,
+    /// How much unfocused windows get darkened, from 0 (not at all) to 1
+    /// (black)
+    pub inactive_dim: f32,
+    /// Accessibility filters applied to the whole screen, in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub color_filters: Vec<ColorFilter>,
+    pub shadow: ShadowConfig,
+    pub blur: BlurConfig,
+    pub corners: CornerConfig,
+    pub fade: FadeConfig,
+    pub animation: AnimationConfig,
+    pub night_light: NightLightConfig,
+    pub lut: LutConfig,
+    pub opengl: OpenglConfig,
+    pub headless: HeadlessConfig,
+    /// Per-window settings, see `rules::Rule`.
+    /// Kept last since TOML tables have to follow plain values.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            backend: BackendKind::Opengl,
+            frame_time: None,
+            vsync: Vsync::Auto,
+            log_level: Level::Info,
+            screen_size: None,
+            inactive_dim: 0.0,
+            color_filters: Vec::new(),
+            shadow: Default::default(),
+            blur: Default::default(),
+            corners: Default::default(),
+            fade: Default::default(),
+            animation: Default::default(),
+            night_light: Default::default(),
+            lut: Default::default(),
+            opengl: Default::default(),
+            headless: Default::default(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// OpenGL, falls back to xrender if unavailable
+    Opengl,
+    Xrender,
+    Headless,
+}
+
+/// How buffer swaps get synchronized with the display refresh, written
+/// as `true`, `false`, `"on"`, `"off"` or the name of a method
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Vsync {
+    Off,
+    /// First of the methods below the server supports
+    Auto,
+    /// Swap interval of GLX_EXT_swap_control
+    Ext,
+    /// Swap interval of GLX_MESA_swap_control
+    Mesa,
+    /// Waiting for the vertical blank with GLX_SGI_video_sync
+    Sgi,
+}
+
+impl FromStr for Vsync {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Vsync, String> {
+        match s {
+            "on" | "true" | "yes" | "1" | "auto" => Ok(Vsync::Auto),
+            "off" | "false" | "no" | "0" => Ok(Vsync::Off),
+            "ext" => Ok(Vsync::Ext),
+            "mesa" => Ok(Vsync::Mesa),
+            "sgi" => Ok(Vsync::Sgi),
+            _ => Err(format!(
+                "invalid vsync mode `{}`, expected one of: on, off, ext, \
+                 mesa, sgi",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Vsync {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Vsync, D::Error> {
+        struct VsyncVisitor;
+
+        impl Visitor<'_> for VsyncVisitor {
+            type Value = Vsync;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "true, false or a vsync mode")
+            }
+
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<Vsync, E> {
+                Ok(if v { Vsync::Auto } else { Vsync::Off })
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Vsync, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        d.deserialize_any(VsyncVisitor)
+    }
+}
+
+impl Serialize for Vsync {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Vsync::Off => s.serialize_bool(false),
+            Vsync::Auto => s.serialize_bool(true),
+            method => s.serialize_str(&method.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Vsync {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Vsync::Off => "off",
+            Vsync::Auto => "auto",
+            Vsync::Ext => "ext",
+            Vsync::Mesa => "mesa",
+            Vsync::Sgi => "sgi",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShadowConfig {
+    /// Draw shadows beneath windows, rules can override it per window
+    pub enabled: bool,
+    /// Blur radius, in pixels
+    pub radius: u16,
+    /// Position of the shadow relative to the window, [x, y] in pixels
+    pub offset: [i16; 2],
+    pub color: Color,
+    pub opacity: f32,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> ShadowConfig {
+        ShadowConfig {
+            enabled: false,
+            radius: 12,
+            offset: [4, 4],
+            color: Color { red: 0, green: 0, blue: 0 },
+            opacity: 0.5,
+        }
+    }
+}
+
+/// Blur of what is behind translucent windows, enabled per window with
+/// rules
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlurConfig {
+    /// Number of blur passes, from 1 to 6
+    pub strength: u8,
+}
+
+impl Default for BlurConfig {
+    fn default() -> BlurConfig {
+        BlurConfig { strength: 3 }
+    }
+}
+
+/// Rounded window corners, rules can turn them off per window
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CornerConfig {
+    /// Corner radius in pixels, 0 keeps corners square
+    pub radius: u16,
+    /// Width of the border drawn along the edge of rounded windows, in
+    /// pixels
+    pub border_width: u16,
+    pub border_color: Color,
+}
+
+impl Default for CornerConfig {
+    fn default() -> CornerConfig {
+        CornerConfig {
+            radius: 0,
+            border_width: 0,
+            border_color: Color { red: 0xff, green: 0xff, blue: 0xff },
+        }
+    }
+}
+
+/// Opacity animations on map, unmap and opacity changes
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FadeConfig {
+    pub enabled: bool,
+    /// Length of a fade, in milliseconds
+    pub duration: u64,
+    /// Time between two frames of a fade, in milliseconds
+    pub step: u64,
+}
+
+impl Default for FadeConfig {
+    fn default() -> FadeConfig {
+        FadeConfig { enabled: false, duration: 150, step: 10 }
+    }
+}
+
+/// Open and close animations on map and unmap
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnimationConfig {
+    pub enabled: bool,
+    /// Length of an animation, in milliseconds
+    pub duration: u64,
+    pub curve: Curve,
+    /// Animation used for each `_NET_WM_WINDOW_TYPE`, lowercased without
+    /// the prefix. Types left out are not animated.
+    pub types: BTreeMap<String, Kind>,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> AnimationConfig {
+        let types = [
+            ("normal", Kind::Scale),
+            ("dialog", Kind::Zoom),
+            ("menu", Kind::Slide),
+            ("dropdown_menu", Kind::Slide),
+            ("popup_menu", Kind::Slide),
+        ];
+        AnimationConfig {
+            enabled: false,
+            duration: 200,
+            // CSS `ease-out`
+            curve: Curve::CubicBezier([0.0, 0.0, 0.58, 1.0]),
+            types: types.iter().map(|(t, k)| (t.to_string(), *k)).collect(),
+        }
+    }
+}
+
+/// Colour temperature of the whole screen, following a daily schedule
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NightLightConfig {
+    pub enabled: bool,
+    /// Colour temperature at night, in Kelvin
+    pub temperature: u32,
+    /// Colour temperature during the day, 6500 leaves colours untouched
+    pub day_temperature: u32,
+    /// When the night starts, it never ends without a schedule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sunset: Option<TimeOfDay>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sunrise: Option<TimeOfDay>,
+    /// Time the temperature takes to shift after sunset and sunrise, in
+    /// minutes
+    pub transition: u32,
+}
+
+impl Default for NightLightConfig {
+    fn default() -> NightLightConfig {
+        NightLightConfig {
+            enabled: false,
+            temperature: 4500,
+            day_temperature: 6500,
+            sunset: None,
+            sunrise: None,
+            transition: 30,
+        }
+    }
+}
+
+/// Colour correction through 3D LUTs read from `.cube` files, ignored by
+/// the xrender backend
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LutConfig {
+    /// LUT of the outputs without one of their own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    /// LUT of each RandR output, by name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub outputs: BTreeMap<String, PathBuf>,
+}
+
+/// An RGB colour, written as `"#rrggbb"`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Color {
+    /// Components between 0 and 1
+    pub fn to_f32(self) -> [f32; 3] {
+        [self.red, self.green, self.blue].map(|c| c as f32 / 255.0)
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Color, String> {
+        let hex = value
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6 && hex.is_ascii())
+            .ok_or_else(|| {
+                format!("invalid colour `{}`, expected \"#rrggbb\"", value)
+            })?;
+        let component = |i: usize| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| format!("invalid colour `{}`", value))
+        };
+        Ok(Color {
+            red: component(0)?,
+            green: component(2)?,
+            blue: component(4)?,
+        })
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> String {
+        format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpenglConfig {
+    /// Filtering used when sampling window textures
+    pub filter: Filter,
+    /// Version of the requested OpenGL context, [major, minor]
+    pub context_version: [u8; 2],
+    /// Fragment shaders run over every frame, in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub post: Vec<PathBuf>,
+    /// Waiting for the GPU to finish a frame before swapping buffers
+    pub swap_sync: SwapSync,
+}
+
+impl Default for OpenglConfig {
+    fn default() -> OpenglConfig {
+        OpenglConfig {
+            filter: Filter::Nearest,
+            context_version: [3, 3],
+            post: Vec::new(),
+            swap_sync: SwapSync::None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SwapSync {
+    None,
+    /// `glFinish`
+    Finish,
+    /// Waiting on a fence sync object
+    Fence,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeadlessConfig {
+    /// Directory every presented frame gets written to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_dir: Option<PathBuf>,
+}
+
+/// Error while loading a config file, points to the offending line
+/// when it is known.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => {
+                write!(f, "{}:{}: {}", self.path.display(), line, self.message)
+            }
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config from `path`, or from the default location if no
+    /// path is given.
+    ///
+    /// A missing file at the default location is not an error, the
+    /// default config is used instead.
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let source = fs::read_to_string(&path).map_err(|e| ConfigError {
+            path: path.clone(),
+            line: None,
+            message: e.to_string(),
+        })?;
+        let mut config =
+            Config::parse(&source).map_err(|(line, message)| ConfigError {
+                path: path.clone(),
+                line,
+                message,
+            })?;
+        if let Some(dir) = path.parent() {
+            config.resolve_shaders(dir);
+        }
+        Ok(config)
+    }
+
+    /// Makes relative shader paths relative to `dir`, the directory of
+    /// the config file, instead of the working directory
+    fn resolve_shaders(&mut self, dir: &Path) {
+        let shaders = self
+            .rules
+            .iter_mut()
+            .filter_map(|rule| rule.set.shader.as_mut())
+            .chain(&mut self.opengl.post);
+        for shader in shaders {
+            if shader.is_relative() {
+                *shader = dir.join(&*shader);
+            }
+        }
+    }
+
+    /// Parses and validates a config from its TOML source.
+    /// Errors are returned along with their line number.
+    pub fn parse(source: &str) -> Result<Config, (Option<usize>, String)> {
+        let config: Config = toml::from_str(source).map_err(|e| {
+            let line = e.line_col().map(|(line, _)| line + 1);
+            // The line is reported separately
+            let message = e.to_string();
+            let message = match message.find(" at line ") {
+                Some(i) => message[..i].to_string(),
+                None => message,
+            };
+            (line, message)
+        })?;
+        config.validate().map_err(|(key, message)| {
+            (
+                find_key_line(source, key),
+                format!("{} for key `{}`", message, key),
+            )
+        })?;
+        for (i, rule) in config.rules.iter().enumerate() {
+            rule.set.validate().map_err(|(key, message)| {
+                (
+                    find_rule_key_line(source, i, key),
+                    format!("{} for key `rules.set.{}`", message, key),
+                )
+            })?;
+        }
+        Ok(config)
+    }
+
+    /// Checks values that are well-formed but unusable, rules being
+    /// checked on their own.
+    /// Returns the full key of the bad value along with the reason.
+    fn validate(&self) -> Result<(), (&'static str, &'static str)> {
+        if let Some(frame_time) = self.frame_time {
+            if frame_time == 0 || frame_time > 1000 {
+                return Err(("frame_time", "must be between 1 and 1000"));
+            }
+        }
+        if let Some([width, height]) = self.screen_size {
+            if width == 0 || height == 0 {
+                return Err(("screen_size", "must not be zero"));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.inactive_dim) {
+            return Err(("inactive_dim", "must be between 0 and 1"));
+        }
+        if self.shadow.radius > 256 {
+            return Err(("shadow.radius", "must be at most 256"));
+        }
+        if !(0.0..=1.0).contains(&self.shadow.opacity) {
+            return Err(("shadow.opacity", "must be between 0 and 1"));
+        }
+        if !(1..=6).contains(&self.blur.strength) {
+            return Err(("blur.strength", "must be between 1 and 6"));
+        }
+        if self.corners.radius > 256 {
+            return Err(("corners.radius", "must be at most 256"));
+        }
+        if self.corners.border_width > 64 {
+            return Err(("corners.border_width", "must be at most 64"));
+        }
+        if self.fade.duration > 10_000 {
+            return Err(("fade.duration", "must be at most 10000"));
+        }
+        if self.fade.step == 0 || self.fade.step > 1000 {
+            return Err(("fade.step", "must be between 1 and 1000"));
+        }
+        if self.animation.duration > 10_000 {
+            return Err(("animation.duration", "must be at most 10000"));
+        }
+        let temperatures = 1000..=25_000;
+        if !temperatures.contains(&self.night_light.temperature) {
+            return Err((
+                "night_light.temperature",
+                "must be between 1000 and 25000",
+            ));
+        }
+        if !temperatures.contains(&self.night_light.day_temperature) {
+            return Err((
+                "night_light.day_temperature",
+                "must be between 1000 and 25000",
+            ));
+        }
+        match (self.night_light.sunset, self.night_light.sunrise) {
+            (None, None) => (),
+            (Some(sunset), Some(sunrise)) => {
+                let night =
+                    (sunrise.seconds + 86_400 - sunset.seconds) % 86_400;
+                let shortest = night.min(86_400 - night);
+                if night == 0 {
+                    return Err((
+                        "night_light.sunrise",
+                        "must differ from sunset",
+                    ));
+                }
+                if self.night_light.transition.saturating_mul(60) > shortest {
+                    return Err((
+                        "night_light.transition",
+                        "must be shorter than both the night and the day",
+                    ));
+                }
+            }
+            _ => {
+                return Err((
+                    "night_light.sunrise",
+                    "must be set along with sunset",
+                ))
+            }
+        }
+        if self.opengl.context_version < [3, 3] {
+            return Err(("opengl.context_version", "must be at least 3.3"));
+        }
+        Ok(())
+    }
+}
+
+/// `$XDG_CONFIG_HOME/rcomp/rcomp.toml`, or `~/.config/rcomp/rcomp.toml`
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("rcomp").join("rcomp.toml"))
+}
+
+/// Finds the line where a (dotted) key is set in a TOML source.
+///
+/// Only handles keys set inside their own `[table]`, which is what the
+/// sample config uses.
+fn find_key_line(source: &str, key: &str) -> Option<usize> {
+    let (table, name) = match key.rfind('.') {
+        Some(i) => (&key[..i], &key[i + 1..]),
+        None => ("", key),
+    };
+    let mut current_table = "";
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            current_table = line.trim_matches(|c| c == '[' || c == ']').trim();
+        } else if current_table == table {
+            if let Some((k, _)) = line.split_once('=') {
+                if k.trim().trim_matches('"') == name {
+                    return Some(i + 1);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Finds the line where `name` is set in the `set` table of the rule at
+/// `index`, written inline or under `[rules.set]`.
+///
+/// Only handles rules written as `[[rules]]` tables.
+fn find_rule_key_line(
+    source: &str,
+    index: usize,
+    name: &str,
+) -> Option<usize> {
+    // Not followed by another `=` or `~`, which are match operators
+    let key = Regex::new(&format!(
+        r"(^|[{{,\s]){}\s*=([^=~]|$)",
+        regex::escape(name)
+    ))
+    .ok()?;
+    let mut current_rule = None;
+    let mut count = 0;
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            let table = line.trim_matches(|c| c == '[' || c == ']').trim();
+            if line.starts_with("[[") && table == "rules" {
+                current_rule = Some(count);
+                count += 1;
+            } else if !table.starts_with("rules.") {
+                current_rule = None;
+            }
+        } else if current_rule == Some(index) && key.is_match(line) {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_line_of_invalid_key() {
+        let source = "frame_time = 16\n\n[opengl]\ncontext_version = [3, 0]\n";
+        let (line, message) = Config::parse(source).unwrap_err();
+        assert_eq!(line, Some(4));
+        assert_eq!(
+            message,
+            "must be at least 3.3 for key `opengl.context_version`"
+        );
+    }
+
+    #[test]
+    fn reports_line_of_invalid_rule_key() {
+        let source = "\
+[[rules]]
+match = 'name == \"a\"'
+set = { opacity = 0.5 }
+
+[[rules]]
+match = 'class == \"b\"'
+set = { opacity = 1.5 }
+";
+        let (line, message) = Config::parse(source).unwrap_err();
+        assert_eq!(line, Some(7));
+        assert_eq!(
+            message,
+            "must be between 0 and 1 for key `rules.set.opacity`"
+        );
+    }
+
+    #[test]
+    fn reports_line_of_invalid_rule_subtable_key() {
+        let source = "\
+[[rules]]
+match = 'alpha'
+
+[rules.set]
+opacity = -1.0
+";
+        let (line, _) = Config::parse(source).unwrap_err();
+        assert_eq!(line, Some(5));
+    }
+
+    #[test]
+    fn resolves_shaders_from_config_dir() {
+        let source = "\
+[opengl]
+post = [\"post.frag\", \"/usr/share/rcomp/vignette.frag\"]
+
+[[rules]]
+match = 'class == \"mpv\"'
+set = { shader = \"shaders/gray.frag\" }
+";
+        let mut config = Config::parse(source).unwrap();
+        config.resolve_shaders(Path::new("/home/user/.config/rcomp"));
+        assert_eq!(
+            config.opengl.post,
+            [
+                Path::new("/home/user/.config/rcomp/post.frag"),
+                Path::new("/usr/share/rcomp/vignette.frag"),
+            ]
+        );
+        assert_eq!(
+            config.rules[0].set.shader.as_deref(),
+            Some(Path::new("/home/user/.config/rcomp/shaders/gray.frag"))
+        );
+    }
+
+    #[test]
+    fn reads_vsync_modes() {
+        let vsync = |value: &str| {
+            Config::parse(&format!("vsync = {}", value)).map(|c| c.vsync)
+        };
+        assert_eq!(vsync("true"), Ok(Vsync::Auto));
+        assert_eq!(vsync("false"), Ok(Vsync::Off));
+        assert_eq!(vsync("\"on\""), Ok(Vsync::Auto));
+        assert_eq!(vsync("\"off\""), Ok(Vsync::Off));
+        assert_eq!(vsync("\"0\""), Ok(Vsync::Off));
+        assert_eq!(vsync("\"sgi\""), Ok(Vsync::Sgi));
+        assert!(vsync("\"fast\"").is_err());
+    }
+
+    #[test]
+    fn prints_vsync_modes_back() {
+        for mode in
+            [Vsync::Off, Vsync::Auto, Vsync::Ext, Vsync::Mesa, Vsync::Sgi]
+        {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+            let config = Config { vsync: mode, ..Config::default() };
+            let printed = toml::to_string(&config).unwrap();
+            assert_eq!(Config::parse(&printed).unwrap().vsync, mode);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+/// Coverage of the pixels of a window with rounded corners, computed on
+/// the CPU for the backends that can't do it while drawing.
+#[derive(Default)]
+pub struct Corners {
+    pub width: usize,
+    pub height: usize,
+    /// How much of each pixel is inside the window, from 0 to 255
+    pub inside: Vec<u8>,
+    /// How much of each pixel is covered by the border, empty without one
+    pub border: Vec<u8>,
+}
+
+impl Corners {
+    pub fn build(
+        width: usize,
+        height: usize,
+        radius: f32,
+        border_width: f32,
+    ) -> Corners {
+        let mut inside = Vec::with_capacity(width * height);
+        let mut border = Vec::new();
+        if border_width > 0.0 {
+            border.reserve(width * height);
+        }
+        for y in 0..height {
+            for x in 0..width {
+                let dist = distance(
+                    x as f32 + 0.5,
+                    y as f32 + 0.5,
+                    width as f32,
+                    height as f32,
+                    radius,
+                );
+                let covered = coverage(dist);
+                inside.push((covered * 255.0).round() as u8);
+                if border_width > 0.0 {
+                    let stroke = border_coverage(dist, border_width) * covered;
+                    border.push((stroke * 255.0).round() as u8);
+                }
+            }
+        }
+        Corners { width, height, inside, border }
+    }
+}
+
+/// Signed distance from (`x`, `y`) to the edge of a `width`x`height`
+/// rectangle with rounded corners, negative inside
+pub fn distance(x: f32, y: f32, width: f32, height: f32, radius: f32) -> f32 {
+    let qx = (x - width / 2.0).abs() - (width / 2.0 - radius);
+    let qy = (y - height / 2.0).abs() - (height / 2.0 - radius);
+    let outside = qx.max(0.0).hypot(qy.max(0.0));
+    outside + qx.max(qy).min(0.0) - radius
+}
+
+/// Share of a pixel inside the edge, for a pixel centered `dist` from it.
+/// Edges are smoothed over a pixel.
+pub fn coverage(dist: f32) -> f32 {
+    (0.5 - dist).clamp(0.0, 1.0)
+}
+
+/// Share of a pixel centered `dist` from the edge within `width` of it,
+/// on the inside
+pub fn border_coverage(dist: f32, width: f32) -> f32 {
+    (dist + width + 0.5).clamp(0.0, 1.0)
+}
@@ -0,0 +1,91 @@
+use crate::backend::opengl::setup;
+use std::ffi::CStr;
+use xcb::{composite, damage, randr, render, shape, xfixes};
+
+/// Prints information about the X server relevant to compositing,
+/// without changing anything on it.
+pub fn print(conn: &xcb::Connection, xlib_screens: i32) {
+    let setup = conn.get_setup();
+    println!("rcomp {}", env!("CARGO_PKG_VERSION"));
+    println!("X server: {} {}", setup.vendor(), setup.release_number());
+    for (i, screen) in setup.roots().enumerate() {
+        println!(
+            "Screen {}: {}x{}, depth {}",
+            i,
+            screen.width_in_pixels(),
+            screen.height_in_pixels(),
+            screen.root_depth()
+        );
+    }
+
+    println!("Extensions:");
+    let extensions = [
+        ("Composite", composite::id()),
+        ("DAMAGE", damage::id()),
+        ("RANDR", randr::id()),
+        ("RENDER", render::id()),
+        ("SHAPE", shape::id()),
+        ("XFIXES", xfixes::id()),
+    ];
+    for (name, id) in extensions {
+        let present = conn.get_extension_data(id).is_some_and(|e| e.present());
+        println!("  {}: {}", name, yes_no(present));
+    }
+
+    for screen in 0..setup.roots_len() {
+        let atom =
+            xcb::intern_atom(conn, false, &format!("_NET_WM_CM_S{}", screen))
+                .get_reply()
+                .map(|reply| reply.atom());
+        let owner = atom.ok().and_then(|atom| {
+            xcb::get_selection_owner(conn, atom).get_reply().ok()
+        });
+        match owner.map(|reply| reply.owner()) {
+            Some(xcb::NONE) | None => {
+                println!("Compositor running on screen {}: no", screen)
+            }
+            Some(win) => println!(
+                "Compositor running on screen {}: yes (window {})",
+                screen, win
+            ),
+        }
+    }
+
+    let dpy = conn.get_raw_dpy();
+    match setup::glx_dec_version(dpy) {
+        Ok(version) => {
+            println!("GLX: {}.{}", version / 10, version % 10)
+        }
+        Err(err) => {
+            println!("GLX: {}", err);
+            return;
+        }
+    }
+    let glx_exts = unsafe {
+        CStr::from_ptr(setup::glXQueryExtensionsString(dpy, xlib_screens))
+            .to_string_lossy()
+            .into_owned()
+    };
+    for ext in [
+        "GLX_ARB_create_context",
+        "GLX_EXT_texture_from_pixmap",
+        "GLX_EXT_swap_control",
+        "GLX_MESA_swap_control",
+        "GLX_SGI_video_sync",
+        "GLX_EXT_buffer_age",
+    ] {
+        println!(
+            "  {}: {}",
+            ext,
+            yes_no(setup::check_glx_extension(&glx_exts, ext))
+        );
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
@@ -1,123 +1,153 @@
-use crate::opengl::Opengl;
+use crate::anim::{Animation, Kind};
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::region::{self, Region};
+use crate::rules;
+use crate::schedule::Scheduler;
 use crate::state::State;
-use crate::window::Window;
-use xcb::damage;
+use crate::window::{self, Window};
+use std::path::Path;
+use std::process;
+use xcb::{damage, shape, xfixes};
 
 use std::time::{Duration, Instant};
 
-pub fn handle_event(
+/// Time without resize after which the masks of a window are rebuilt,
+/// they are stretched meanwhile
+const RESIZE_SETTLE: Duration = Duration::from_millis(100);
+
+pub fn handle_event<B: Backend>(
     state: &State,
     base_event: xcb::GenericEvent,
-    windows: &mut Vec<Window>,
-    backend: &Opengl,
-    last_render: &mut Instant,
-    refresh_rate: &Duration,
+    windows: &mut Vec<Window<B::Context>>,
+    backend: &B,
+    config: &Config,
+    scheduler: &mut Scheduler,
+    pending: &mut PendingDamage,
 ) {
     match base_event.response_type() {
         // New window created
         xcb::CREATE_NOTIFY => {
-            println!("CREATE_NOTIFY");
+            debug!("CREATE_NOTIFY");
             let ev: &xcb::CreateNotifyEvent =
                 unsafe { xcb::cast_event(&base_event) };
             match Window::new(&state.conn, ev.window()) {
                 Ok(mut win) => {
+                    win.update_client(&state.conn, &state.atoms);
+                    rules::apply(&config.rules, &mut win);
                     if win.mapped {
                         backend.init_window(&mut win);
                         backend.update_pos(&win);
                         backend.update_pixmap(&mut win);
                         backend.update_texture(&mut win);
+                        backend.update_masks(&mut win);
                     }
                     windows.push(win);
                 }
                 Err(e) => {
-                    println!("CreateNotify: cannot get window info: {}", e)
+                    warn!("CreateNotify: cannot get window info: {}", e)
                 }
             };
-            for win in windows.iter_mut().filter(|w| w.mapped) {
-                backend.draw_window(win);
-            }
-            backend.render();
+            scheduler.request_full();
         }
         // Window destroyed
         // For any window, an event for every child is sent out first
         xcb::DESTROY_NOTIFY => {
-            println!("DESTROY_NOTIFY");
+            debug!("DESTROY_NOTIFY");
             let ev: &xcb::DestroyNotifyEvent =
                 unsafe { xcb::cast_event(&base_event) };
-            windows.retain(|w| w.id != ev.window());
-
-            for win in windows.iter_mut().filter(|w| w.mapped) {
-                backend.draw_window(win);
+            match windows.iter_mut().find(|w| w.id == ev.window()) {
+                // Its pixmap outlives it, so it can still animate out
+                Some(w) if w.animating() => w.destroyed = true,
+                _ => windows.retain(|w| w.id != ev.window()),
             }
-            backend.render();
+
+            scheduler.request_full();
         }
         // Window property(size, border, position, stack order) changed
         // TODO: check if window is root
         xcb::CONFIGURE_NOTIFY => {
-            println!("CONFIGURE_NOTIFY");
+            debug!("CONFIGURE_NOTIFY");
             let ev: &xcb::ConfigureNotifyEvent =
                 unsafe { xcb::cast_event(&base_event) };
             let win_id = ev.window();
             if let Some(i) = windows.iter().position(|w| w.id == win_id) {
                 let w = &mut windows[i];
                 // New pixmap is generated for every resize
-                if w.mapped && ev.width() != w.width
-                    || ev.height() != w.height
-                    || ev.override_redirect() != w.override_redirect
-                    || ev.border_width() != w.border_width
-                {
+                let resized = w.mapped
+                    && (ev.width() != w.width
+                        || ev.height() != w.height
+                        || ev.override_redirect() != w.override_redirect
+                        || ev.border_width() != w.border_width);
+                if resized {
                     backend.update_pixmap(w);
                     backend.update_texture(w);
                 }
+                let masks = mask_settings(w, config);
                 w.update_using_event(ev);
+                if resized && w.blur_region.is_some() {
+                    w.update_client_offset(&state.conn);
+                }
+                rules::apply(&config.rules, w);
+                if w.mapped && mask_settings(w, config) != masks {
+                    backend.update_masks(w);
+                    w.resized = None;
+                } else if resized {
+                    // Rebuilt once the resize settles, interactive ones
+                    // sending an event for every step
+                    w.resized = Some(Instant::now());
+                }
                 backend.update_pos(w);
                 restack_window(win_id, ev.above_sibling(), windows);
             } else if win_id == state.root.id {
                 //backend.update_pixmap(win_id);
                 //backend.update_texture(win_id);
             } else {
-                println!("ConfigureEvent: No window in list: {}", win_id);
-            }
-            for win in windows.iter_mut().filter(|w| w.mapped) {
-                backend.draw_window(win);
+                debug!("ConfigureEvent: No window in list: {}", win_id);
             }
-            backend.render();
+            scheduler.request_full();
         }
         // Existing window mapped
         xcb::MAP_NOTIFY => {
-            println!("MAP_NOTIFY");
+            debug!("MAP_NOTIFY");
             let ev: &xcb::MapNotifyEvent =
                 unsafe { xcb::cast_event(&base_event) };
             if let Some(i) = windows.iter().position(|w| w.id == ev.window()) {
                 let w = &mut windows[i];
+                // Fading out windows fade back in from where they are
+                let from =
+                    if w.fade.is_some() { w.draw_opacity() } else { 0.0 };
                 w.mapped = true;
+                // The window manager may have reparented a client into it
+                w.update_client(&state.conn, &state.atoms);
+                rules::apply(&config.rules, w);
+                w.start_fade(from, w.target_opacity(), fade_duration(config));
+                start_animation(w, config, false);
                 backend.init_window(w);
                 // New pixmap is generated for every map
                 backend.update_pos(w);
                 backend.update_pixmap(w);
                 backend.update_texture(w);
-                for win in windows.iter_mut().filter(|w| w.mapped) {
-                    backend.draw_window(win);
-                }
-                backend.render();
+                backend.update_masks(w);
+                scheduler.request_full();
             }
         }
         // Existing window unmapped
         xcb::UNMAP_NOTIFY => {
-            println!("UNMAP_NOTIFY");
+            debug!("UNMAP_NOTIFY");
             let ev: &xcb::UnmapNotifyEvent =
                 unsafe { xcb::cast_event(&base_event) };
             if let Some(i) = windows.iter().position(|w| w.id == ev.window()) {
-                windows[i].mapped = false;
-                for win in windows.iter_mut().filter(|w| w.mapped) {
-                    backend.draw_window(win);
-                }
-                backend.render();
+                let w = &mut windows[i];
+                w.mapped = false;
+                w.start_fade(w.draw_opacity(), 0.0, fade_duration(config));
+                start_animation(w, config, true);
+                scheduler.request_full();
             }
         }
         // Window's parent changed
         xcb::REPARENT_NOTIFY => {
-            println!("REPARENT_NOTIFY");
+            debug!("REPARENT_NOTIFY");
             let event: &xcb::ReparentNotifyEvent =
                 unsafe { xcb::cast_event(&base_event) };
             let win_id = event.window();
@@ -125,26 +155,36 @@ pub fn handle_event(
                 if windows.iter().position(|w| w.id == win_id).is_none() {
                     match Window::new(&state.conn, win_id) {
                         Ok(mut win) => {
+                            win.update_client(&state.conn, &state.atoms);
+                            rules::apply(&config.rules, &mut win);
                             if win.mapped {
                                 backend.update_pos(&win);
                                 backend.update_pixmap(&mut win);
                                 backend.update_texture(&mut win);
+                                backend.update_masks(&mut win);
                             }
                             windows.push(win);
                         }
                         Err(e) => {
-                            println!("Cannot get created window info: {}", e)
+                            warn!("Cannot get created window info: {}", e)
                         }
                     };
                 }
             } else {
                 windows.retain(|w| w.id != win_id);
+                // Client reparented into a frame, match the frame on it
+                if let Some(frame) =
+                    windows.iter_mut().find(|w| w.id == event.parent())
+                {
+                    frame.update_client(&state.conn, &state.atoms);
+                    rules::apply(&config.rules, frame);
+                }
             }
         }
         // Window's stack position changed
         // Currently does not do anything useful
         xcb::CIRCULATE_NOTIFY => {
-            println!("CIRCULATE_NOTIFY");
+            debug!("CIRCULATE_NOTIFY");
             let ev: &xcb::CirculateNotifyEvent =
                 unsafe { xcb::cast_event(&base_event) };
             let win_above = if ev.place() == xcb::PLACE_ON_TOP as u8 {
@@ -153,14 +193,11 @@ pub fn handle_event(
                 xcb::NONE
             };
             restack_window(ev.window(), win_above, windows);
-            for win in windows.iter_mut().filter(|w| w.mapped) {
-                backend.draw_window(win);
-            }
-            backend.render();
+            scheduler.request_full();
         }
         // Window unhidden
         xcb::EXPOSE => {
-            println!("EXPOSE");
+            debug!("EXPOSE");
             let ev: &xcb::ExposeEvent =
                 unsafe { xcb::cast_event(&base_event) };
             // TODO: check if window is root
@@ -171,53 +208,329 @@ pub fn handle_event(
             if ev.count() != 0 {
                 return;
             }
-            for win in windows.iter_mut().filter(|w| w.mapped) {
-                backend.draw_window(win);
-            }
-            backend.render();
+            scheduler.request_full();
         }
         // Window property(atom) changed
-        // TODO
         xcb::PROPERTY_NOTIFY => {
-            println!("PROPERTY_NOTIFY");
-            let _ev: &xcb::PropertyNotifyEvent =
+            debug!("PROPERTY_NOTIFY");
+            let ev: &xcb::PropertyNotifyEvent =
+                unsafe { xcb::cast_event(&base_event) };
+            if ev.window() == state.root.id {
+                if ev.atom() == state.atoms.rcomp_screenshot
+                    && ev.state() == xcb::PROPERTY_NEW_VALUE as u8
+                {
+                    save_frame(state, backend);
+                } else if ev.atom() == state.atoms.net_active_window {
+                    let active = window::fetch_active(
+                        &state.conn,
+                        &state.atoms,
+                        state.root.id,
+                    );
+                    if window::set_focus(windows, active)
+                        && config.inactive_dim > 0.0
+                    {
+                        scheduler.request_full();
+                    }
+                }
+            } else if let Some(w) = windows
+                .iter_mut()
+                .find(|w| w.id == ev.window() || w.client == ev.window())
+            {
+                let (opacity, dimmed) = (w.draw_opacity(), w.dimmed());
+                let target = w.target_opacity();
+                let masks = mask_settings(w, config);
+                let color_matrix = w.color_matrix();
+                if !w.update_property(&state.conn, &state.atoms, ev.atom()) {
+                    return;
+                }
+                rules::apply(&config.rules, w);
+                if w.mapped && w.target_opacity() != target {
+                    w.start_fade(
+                        opacity,
+                        w.target_opacity(),
+                        fade_duration(config),
+                    );
+                }
+                let masks_changed = mask_settings(w, config) != masks;
+                if w.mapped && masks_changed {
+                    backend.update_masks(w);
+                }
+                let blur_changed =
+                    ev.atom() == state.atoms.kde_net_wm_blur_behind_region;
+                // Title changes and such don't need a redraw
+                if w.mapped
+                    && (w.draw_opacity() != opacity
+                        || w.dimmed() != dimmed
+                        || masks_changed
+                        || blur_changed
+                        || w.color_matrix() != color_matrix)
+                {
+                    scheduler.request_full();
+                }
+            }
+        }
+        // Window bounding shape changed
+        response_type if response_type == state.shape_event => {
+            debug!("SHAPE_NOTIFY");
+            let ev: &shape::NotifyEvent =
+                unsafe { xcb::cast_event(&base_event) };
+            if ev.shape_kind() != shape::SK_BOUNDING as u8 {
+                return;
+            }
+            if let Some(w) =
+                windows.iter_mut().find(|w| w.id == ev.affected_window())
+            {
+                w.update_shape(&state.conn);
+                if w.mapped {
+                    backend.update_masks(w);
+                    scheduler.request_full();
+                }
+            }
+        }
+        // Window damage detected
+        response_type if response_type == state.damage_event => {
+            let event: &damage::NotifyEvent =
+                unsafe { xcb::cast_event(&base_event) };
+            if let Some(i) =
+                windows.iter().position(|w| w.id == event.drawable())
+            {
+                backend.update_texture(&mut windows[i]);
+                pending.add(state, event.damage(), windows[i].id);
+            }
+        }
+        // Another compositor was started with `--replace`
+        xcb::SELECTION_CLEAR => {
+            debug!("SELECTION_CLEAR");
+            let ev: &xcb::SelectionClearEvent =
                 unsafe { xcb::cast_event(&base_event) };
+            if ev.owner() == state.win_id {
+                info!("Replaced by another compositor, exiting");
+                state.release();
+                process::exit(0);
+            }
         }
         // TODO: check for root property changes
-        _ => {
-            // Window damage detected
-            if base_event.response_type() == damage::NOTIFY {
-                let event: &damage::NotifyEvent =
-                    unsafe { xcb::cast_event(&base_event) };
-                damage::subtract(
-                    &state.conn,
-                    event.damage(),
-                    xcb::NONE,
-                    xcb::NONE,
-                )
-                .request_check()
-                .unwrap();
-                if let Some(i) =
-                    windows.iter().position(|w| w.id == event.drawable())
-                {
-                    backend.update_texture(&mut windows[i]);
+        _ => {}
+    }
+}
+
+/// Damage moved out of windows by the events handled so far, as XFixes
+/// regions. Their rects are fetched together afterwards, which costs a
+/// single round trip.
+#[derive(Default)]
+pub struct PendingDamage(Vec<(xcb::Window, xfixes::Region)>);
+
+impl PendingDamage {
+    /// Moves the damage of `damage` into the region of `window`, which
+    /// also lets the next damage be reported
+    fn add(
+        &mut self,
+        state: &State,
+        damage: damage::Damage,
+        window: xcb::Window,
+    ) {
+        let conn = &state.conn;
+        match self.0.iter().find(|(id, _)| *id == window) {
+            Some(&(_, region)) => {
+                damage::subtract(conn, damage, xcb::NONE, state.damage_region);
+                xfixes::union_region(
+                    conn,
+                    region,
+                    state.damage_region,
+                    region,
+                );
+            }
+            None => {
+                let region = conn.generate_id();
+                xfixes::create_region(conn, region, &[]);
+                damage::subtract(conn, damage, xcb::NONE, region);
+                self.0.push((window, region));
+            }
+        }
+    }
+
+    /// Asks for a frame redrawing the damaged rects of every window, all
+    /// of the screen for those whose region can't be fetched
+    pub fn fetch<C: Default>(
+        &mut self,
+        state: &State,
+        windows: &[Window<C>],
+        scheduler: &mut Scheduler,
+    ) {
+        // Every request is sent before waiting for the first reply
+        let fetches: Vec<_> = self
+            .0
+            .drain(..)
+            .map(|(id, region)| {
+                (id, region, xfixes::fetch_region(&state.conn, region))
+            })
+            .collect();
+        for (id, region, cookie) in fetches {
+            let reply = cookie.get_reply();
+            xfixes::destroy_region(&state.conn, region);
+            let win = match windows.iter().find(|w| w.id == id) {
+                Some(win) => win,
+                None => continue,
+            };
+            match reply {
+                Ok(reply) => {
+                    scheduler.request(damaged_area(win, reply.rectangles()))
                 }
-                if last_render.elapsed() > *refresh_rate {
-                    for win in windows.iter_mut().filter(|w| w.mapped) {
-                        backend.draw_window(win);
-                    }
-                    backend.render();
-                    *last_render = Instant::now();
+                Err(e) => {
+                    warn!("Cannot fetch damaged region: {}", e);
+                    scheduler.request_full();
                 }
             }
         }
     }
 }
 
-fn restack_window(
+/// Part of the screen covered by `rects`, damaged parts of `win`
+fn damaged_area<C: Default>(
+    win: &Window<C>,
+    rects: impl Iterator<Item = xcb::Rectangle>,
+) -> Region {
+    // Relative to the inner corner of the window, inside its border
+    let border = win.border_width as f32;
+    let mut area = Vec::new();
+    for rect in rects {
+        region::insert(
+            &mut area,
+            region::enclosing(win.draw_rect(
+                rect.x() as f32 + border,
+                rect.y() as f32 + border,
+                rect.width() as f32,
+                rect.height() as f32,
+            )),
+        );
+    }
+    Region::Rects(area)
+}
+
+/// Advances the fades and animations in progress. Destroyed windows are
+/// dropped once their animations are over.
+pub fn step_animations<B: Backend>(
+    windows: &mut Vec<Window<B::Context>>,
+    backend: &B,
+) {
+    let now = Instant::now();
+    for win in windows.iter_mut() {
+        win.step_fade(now);
+        if win.anim.is_some() {
+            win.step_anim(now);
+            // Also puts the window back in place once the animation ends
+            backend.update_pos(win);
+        }
+    }
+    windows.retain(|w| !w.destroyed || w.animating());
+}
+
+/// Rebuilds the masks of the windows whose resize settled, returns true
+/// if any was
+pub fn rebuild_resized_masks<B: Backend>(
+    windows: &mut [Window<B::Context>],
+    backend: &B,
+) -> bool {
+    let mut rebuilt = false;
+    for win in windows.iter_mut() {
+        if win.resized.is_some_and(|at| at.elapsed() >= RESIZE_SETTLE) {
+            win.resized = None;
+            if win.mapped {
+                backend.update_masks(win);
+                rebuilt = true;
+            }
+        }
+    }
+    rebuilt
+}
+
+/// Time left until the next resize settles, `None` without any
+pub fn resize_timeout<C>(windows: &[Window<C>]) -> Option<Duration> {
+    windows
+        .iter()
+        .filter_map(|w| w.resized)
+        .map(|at| {
+            (at + RESIZE_SETTLE).saturating_duration_since(Instant::now())
+        })
+        .min()
+}
+
+/// Draws every visible window over `damage`, then presents the frame
+pub fn draw_frame<B: Backend>(
+    windows: &[Window<B::Context>],
+    backend: &B,
+    damage: Region,
+) {
+    backend.set_damage(damage, windows);
+    for win in windows.iter().filter(|w| w.visible()) {
+        backend.draw_window(win);
+    }
+    backend.render();
+}
+
+fn fade_duration(config: &Config) -> Duration {
+    if config.fade.enabled {
+        Duration::from_millis(config.fade.duration)
+    } else {
+        Duration::ZERO
+    }
+}
+
+/// Settings the shadow and corner masks of a window are built from
+fn mask_settings<C: Default>(
+    win: &Window<C>,
+    config: &Config,
+) -> (bool, bool) {
+    (win.has_shadow(config.shadow.enabled), win.rounded())
+}
+
+/// Starts the open or close animation set for the type of the window
+fn start_animation<C: Default>(
+    win: &mut Window<C>,
+    config: &Config,
+    closing: bool,
+) {
+    let anim = &config.animation;
+    let kind =
+        anim.types.get(&win.props.window_type).copied().unwrap_or(Kind::None);
+    win.anim = if anim.enabled && kind != Kind::None && anim.duration > 0 {
+        let duration = Duration::from_millis(anim.duration);
+        Some(Animation::new(kind, anim.curve, duration, closing))
+    } else {
+        None
+    };
+}
+
+/// Saves the last frame to the path set in the `_RCOMP_SCREENSHOT`
+/// root property
+fn save_frame<B: Backend>(state: &State, backend: &B) {
+    let path = match xcb::get_property(
+        &state.conn,
+        false,
+        state.root.id,
+        state.atoms.rcomp_screenshot,
+        xcb::ATOM_ANY,
+        0,
+        1024,
+    )
+    .get_reply()
+    {
+        Ok(reply) => String::from_utf8_lossy(reply.value::<u8>()).into_owned(),
+        Err(e) => {
+            warn!("Cannot get screenshot path: {}", e);
+            return;
+        }
+    };
+    match backend.save_frame(Path::new(&path)) {
+        Ok(()) => info!("Saved frame to {}", path),
+        Err(e) => warn!("Unable to save frame to {}: {}", path, e),
+    }
+}
+
+fn restack_window<C>(
     window: xcb::Window,
     above: xcb::Window,
-    list: &mut Vec<Window>,
+    list: &mut Vec<Window<C>>,
 ) {
     if let Some(i) = list.iter().position(|w| w.id == window) {
         let win = list.remove(i);
@@ -226,7 +539,7 @@ fn restack_window(
             if let Some(pos) = list.iter().position(|w| w.id == above) {
                 list.insert(pos + 1, win);
             } else {
-                println!("Invalid above window: {}", above);
+                debug!("Invalid above window: {}", above);
                 list.push(win);
             }
         } else {
@@ -0,0 +1,328 @@
+use crate::config::Config;
+use crate::state::State;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Accessibility colour filter, applied to the whole screen or to single
+/// windows
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ColorFilter {
+    Invert,
+    Grayscale,
+    HighContrast,
+    /// Shifts the colours people without red cones can't tell apart
+    /// towards ones they can (daltonization)
+    CorrectProtanopia,
+    CorrectDeuteranopia,
+    CorrectTritanopia,
+    /// Shows colours as people without red cones see them
+    SimulateProtanopia,
+    SimulateDeuteranopia,
+    SimulateTritanopia,
+}
+
+const NAMES: [(&str, ColorFilter); 9] = [
+    ("invert", ColorFilter::Invert),
+    ("grayscale", ColorFilter::Grayscale),
+    ("high-contrast", ColorFilter::HighContrast),
+    ("correct-protanopia", ColorFilter::CorrectProtanopia),
+    ("correct-deuteranopia", ColorFilter::CorrectDeuteranopia),
+    ("correct-tritanopia", ColorFilter::CorrectTritanopia),
+    ("simulate-protanopia", ColorFilter::SimulateProtanopia),
+    ("simulate-deuteranopia", ColorFilter::SimulateDeuteranopia),
+    ("simulate-tritanopia", ColorFilter::SimulateTritanopia),
+];
+
+impl FromStr for ColorFilter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ColorFilter, String> {
+        NAMES
+            .iter()
+            .find(|(name, _)| *name == value)
+            .map(|(_, filter)| *filter)
+            .ok_or_else(|| {
+                let names: Vec<&str> = NAMES.iter().map(|(n, _)| *n).collect();
+                format!(
+                    "invalid colour filter `{}`, expected one of: {}",
+                    value,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl TryFrom<String> for ColorFilter {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<ColorFilter, String> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for ColorFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, _) =
+            NAMES.iter().find(|(_, filter)| filter == self).unwrap();
+        write!(f, "{}", name)
+    }
+}
+
+impl From<ColorFilter> for String {
+    fn from(filter: ColorFilter) -> String {
+        filter.to_string()
+    }
+}
+
+/// Colour blindness simulation matrices from Machado et al. (2009), at
+/// full severity
+const PROTANOPIA: [[f32; 3]; 3] = [
+    [0.152286, 1.052583, -0.204868],
+    [0.114503, 0.786281, 0.099216],
+    [-0.003882, -0.048116, 1.051998],
+];
+const DEUTERANOPIA: [[f32; 3]; 3] = [
+    [0.367322, 0.860646, -0.227968],
+    [0.280085, 0.672501, 0.047413],
+    [-0.011820, 0.042940, 0.968881],
+];
+const TRITANOPIA: [[f32; 3]; 3] = [
+    [1.255528, -0.076749, -0.178779],
+    [-0.078411, 0.930809, 0.147602],
+    [0.004733, 0.691367, 0.303900],
+];
+
+/// How the colours lost to a red or green deficiency are spread over the
+/// channels still seen
+const RED_GREEN_SHIFT: [[f32; 3]; 3] =
+    [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]];
+/// Same for a blue deficiency
+const BLUE_SHIFT: [[f32; 3]; 3] =
+    [[1.0, 0.0, 0.7], [0.0, 1.0, 0.7], [0.0, 0.0, 0.0]];
+
+/// Affine transform of premultiplied colours: each row gives an output
+/// channel from red, green, blue and alpha, so that offsets scale with
+/// the opacity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorMatrix(pub [[f32; 4]; 3]);
+
+impl ColorMatrix {
+    pub const IDENTITY: ColorMatrix = ColorMatrix([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+    ]);
+
+    /// Applies `filters` one after the other
+    pub fn new(filters: &[ColorFilter]) -> ColorMatrix {
+        filters
+            .iter()
+            .fold(ColorMatrix::IDENTITY, |m, f| ColorMatrix::of(*f).after(m))
+    }
+
+    fn of(filter: ColorFilter) -> ColorMatrix {
+        match filter {
+            ColorFilter::Invert => ColorMatrix([
+                [-1.0, 0.0, 0.0, 1.0],
+                [0.0, -1.0, 0.0, 1.0],
+                [0.0, 0.0, -1.0, 1.0],
+            ]),
+            ColorFilter::Grayscale => {
+                // Rec. 709 luma
+                let luma = [0.2126, 0.7152, 0.0722, 0.0];
+                ColorMatrix([luma; 3])
+            }
+            // Doubles the distance from middle gray
+            ColorFilter::HighContrast => ColorMatrix([
+                [2.0, 0.0, 0.0, -0.5],
+                [0.0, 2.0, 0.0, -0.5],
+                [0.0, 0.0, 2.0, -0.5],
+            ]),
+            ColorFilter::CorrectProtanopia => {
+                daltonize(PROTANOPIA, RED_GREEN_SHIFT)
+            }
+            ColorFilter::CorrectDeuteranopia => {
+                daltonize(DEUTERANOPIA, RED_GREEN_SHIFT)
+            }
+            ColorFilter::CorrectTritanopia => {
+                daltonize(TRITANOPIA, BLUE_SHIFT)
+            }
+            ColorFilter::SimulateProtanopia => linear(PROTANOPIA),
+            ColorFilter::SimulateDeuteranopia => linear(DEUTERANOPIA),
+            ColorFilter::SimulateTritanopia => linear(TRITANOPIA),
+        }
+    }
+
+    /// `self` applied after `first`
+    fn after(self, first: ColorMatrix) -> ColorMatrix {
+        let (a, b) = (self.0, first.0);
+        let mut out = [[0.0; 4]; 3];
+        for (row, out_row) in out.iter_mut().enumerate() {
+            for col in 0..4 {
+                out_row[col] = (0..3).map(|k| a[row][k] * b[k][col]).sum();
+            }
+            out_row[3] += a[row][3];
+        }
+        ColorMatrix(out)
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == ColorMatrix::IDENTITY
+    }
+
+    /// Filtered colour of premultiplied `rgb` with `alpha`, between 0 and
+    /// `alpha`
+    pub fn apply(&self, rgb: [f32; 3], alpha: f32) -> [f32; 3] {
+        self.0.map(|row| {
+            let value = row[0] * rgb[0]
+                + row[1] * rgb[1]
+                + row[2] * rgb[2]
+                + row[3] * alpha;
+            value.clamp(0.0, alpha)
+        })
+    }
+
+    /// 3x3 part in column-major order, as OpenGL expects it
+    pub fn linear_part(&self) -> [f32; 9] {
+        let m = self.0;
+        [
+            m[0][0], m[1][0], m[2][0], m[0][1], m[1][1], m[2][1], m[0][2],
+            m[1][2], m[2][2],
+        ]
+    }
+
+    pub fn offset(&self) -> [f32; 3] {
+        self.0.map(|row| row[3])
+    }
+}
+
+fn linear(m: [[f32; 3]; 3]) -> ColorMatrix {
+    ColorMatrix(m.map(|row| [row[0], row[1], row[2], 0.0]))
+}
+
+/// `c + shift * (c - simulated(c))`, which is linear too
+fn daltonize(simulation: [[f32; 3]; 3], shift: [[f32; 3]; 3]) -> ColorMatrix {
+    let mut out = [[0.0; 4]; 3];
+    for (row, out_row) in out.iter_mut().enumerate() {
+        for col in 0..3 {
+            let identity = if row == col { 1.0 } else { 0.0 };
+            let shifted: f32 = (0..3)
+                .map(|k| {
+                    let lost =
+                        if k == col { 1.0 } else { 0.0 } - simulation[k][col];
+                    shift[row][k] * lost
+                })
+                .sum();
+            out_row[col] = identity + shifted;
+        }
+    }
+    ColorMatrix(out)
+}
+
+/// Filters applied to the whole screen: those set at runtime on the root
+/// window with `_RCOMP_COLOR_FILTERS`, or else the configured ones
+pub fn global(state: &State, config: &Config) -> Vec<ColorFilter> {
+    fetch(&state.conn, state.root.id, state.atoms.rcomp_color_filters)
+        .unwrap_or_else(|| config.color_filters.clone())
+}
+
+/// Checks if an event is a change of the global filters
+pub fn is_toggle(state: &State, event: &xcb::GenericEvent) -> bool {
+    if event.response_type() & !0x80 != xcb::PROPERTY_NOTIFY {
+        return false;
+    }
+    let ev: &xcb::PropertyNotifyEvent = unsafe { xcb::cast_event(event) };
+    ev.window() == state.root.id
+        && ev.atom() == state.atoms.rcomp_color_filters
+}
+
+/// Filters set on `win` with `atom`, a string of names separated by
+/// commas or spaces. `None` when the property is not set, names that
+/// don't exist are reported and skipped.
+pub fn fetch(
+    conn: &xcb::Connection,
+    win: xcb::Window,
+    atom: xcb::Atom,
+) -> Option<Vec<ColorFilter>> {
+    let reply =
+        xcb::get_property(conn, false, win, atom, xcb::ATOM_ANY, 0, 1024)
+            .get_reply()
+            .ok()?;
+    if reply.type_() == xcb::NONE {
+        return None;
+    }
+    let value = String::from_utf8_lossy(reply.value::<u8>()).into_owned();
+    let filters = value
+        .split(|c: char| c == ',' || c.is_whitespace() || c == '\0')
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| match name.parse() {
+            Ok(filter) => Some(filter),
+            Err(e) => {
+                warn!("{}", e);
+                None
+            }
+        })
+        .collect();
+    Some(filters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        assert!(
+            (0..3).all(|i| (a[i] - b[i]).abs() < 1e-4),
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn composes_filters_in_order() {
+        let invert = ColorMatrix::of(ColorFilter::Invert);
+        assert!(invert.after(invert).is_identity());
+        let contrast = ColorMatrix::of(ColorFilter::HighContrast);
+        let rgb = [0.3, 0.5, 0.6];
+        let both = ColorMatrix::new(&[
+            ColorFilter::HighContrast,
+            ColorFilter::Invert,
+        ]);
+        assert_eq!(both, invert.after(contrast));
+        assert_close(
+            both.apply(rgb, 1.0),
+            invert.apply(contrast.apply(rgb, 1.0), 1.0),
+        );
+        assert!(ColorMatrix::new(&[]).is_identity());
+    }
+
+    #[test]
+    fn filters_premultiplied_colours() {
+        let invert = ColorMatrix::of(ColorFilter::Invert);
+        assert_close(invert.apply([0.2, 0.3, 0.4], 0.5), [0.3, 0.2, 0.1]);
+        let contrast = ColorMatrix::of(ColorFilter::HighContrast);
+        // Kept between 0 and the alpha
+        assert_eq!(contrast.apply([0.1, 0.25, 0.5], 0.5), [0.0, 0.25, 0.5]);
+        let gray = ColorMatrix::of(ColorFilter::Grayscale);
+        assert_close(gray.apply([1.0, 1.0, 1.0], 1.0), [1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn daltonizes_lost_colours_only() {
+        let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        assert!(daltonize(identity, RED_GREEN_SHIFT).is_identity());
+        for filter in [
+            ColorFilter::CorrectProtanopia,
+            ColorFilter::CorrectDeuteranopia,
+            ColorFilter::CorrectTritanopia,
+        ] {
+            let matrix = ColorMatrix::of(filter);
+            // Grays are seen as they are
+            assert_close(matrix.apply([0.5; 3], 1.0), [0.5; 3]);
+            assert_ne!(matrix.apply([0.8, 0.2, 0.1], 1.0), [0.8, 0.2, 0.1]);
+        }
+    }
+}
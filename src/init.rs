@@ -1,9 +1,17 @@
 pub mod window {
     use std::process::exit;
+    use std::thread;
+    use std::time::{Duration, Instant};
 
     /// Creates a dummy window, used to get ownership of atoms,
     /// returns the window ID.
-    pub fn create_window(conn: &xcb::Connection) -> xcb::Window {
+    ///
+    /// If `replace` is set, an already running compositor gets replaced
+    /// instead of exiting.
+    pub fn create_window(
+        conn: &xcb::Connection,
+        replace: bool,
+    ) -> xcb::Window {
         let setup = conn.get_setup();
         let win = conn.generate_id();
         let screen = setup.roots().last().unwrap();
@@ -26,12 +34,17 @@ pub mod window {
         );
         // No need to map the window, since we don't need to display anything
         conn.flush();
-        grab_cm_atoms(conn, win, screen_num);
+        grab_cm_atoms(conn, win, screen_num, replace);
         win
     }
 
     /// Try and get the ownership of _NET_WM_CM_Sn atoms, one for each screen.
-    fn grab_cm_atoms(conn: &xcb::Connection, win: xcb::Window, screens: u8) {
+    fn grab_cm_atoms(
+        conn: &xcb::Connection,
+        win: xcb::Window,
+        screens: u8,
+        replace: bool,
+    ) {
         for screen in 0..screens {
             let atom = xcb::intern_atom(
                 conn,
@@ -42,18 +55,23 @@ pub mod window {
             .unwrap()
             .atom();
 
-            if xcb::get_selection_owner(conn, atom)
+            let old_owner = xcb::get_selection_owner(conn, atom)
                 .get_reply()
                 .unwrap()
-                .owner()
-                != xcb::ATOM_NONE
-            {
-                eprintln!("Another compositor is already running");
-                exit(1);
+                .owner();
+            if old_owner != xcb::ATOM_NONE {
+                if !replace {
+                    eprintln!("Another compositor is already running");
+                    exit(1);
+                }
+                info!("Replacing the running compositor");
             }
 
             xcb::set_selection_owner(conn, win, atom, xcb::CURRENT_TIME);
             conn.flush();
+            if old_owner != xcb::ATOM_NONE {
+                wait_for_destroy(conn, old_owner);
+            }
 
             if xcb::get_selection_owner(conn, atom)
                 .get_reply()
@@ -67,6 +85,20 @@ pub mod window {
         }
     }
 
+    /// Waits for the previous selection owner to go away, so it releases
+    /// its window redirection before we request ours.
+    fn wait_for_destroy(conn: &xcb::Connection, win: xcb::Window) {
+        let timeout = Duration::from_secs(5);
+        let start = Instant::now();
+        while xcb::get_window_attributes(conn, win).get_reply().is_ok() {
+            if start.elapsed() > timeout {
+                eprintln!("Previous compositor did not exit");
+                exit(1);
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
     /// Requests for relevant window change & update events
     pub fn request_events(conn: &xcb::Connection) {
         let setup = conn.get_setup();
@@ -87,7 +119,7 @@ pub mod window {
 }
 
 pub mod extensions {
-    use xcb::{composite, damage, randr, shape};
+    use xcb::{composite, damage, randr, shape, xfixes};
     /// Checks that the required extensions are present in the server.
     // TODO: Check extension versions, along with existence
     // TODO: use macros
@@ -108,6 +140,22 @@ pub mod extensions {
         if !conn.get_extension_data(damage::id()).unwrap().present() {
             return Err("damage");
         }
+        if !conn.get_extension_data(xfixes::id()).unwrap().present() {
+            return Err("xfixes");
+        }
+        Ok(())
+    }
+
+    /// Tells the server which versions of XFixes, Damage and RandR are
+    /// used, the first two refusing any other request before that
+    pub fn query_versions(
+        conn: &xcb::Connection,
+    ) -> Result<(), xcb::GenericError> {
+        // Regions came with XFixes 2
+        xfixes::query_version(conn, 2, 0).get_reply()?;
+        damage::query_version(conn, 1, 1).get_reply()?;
+        // For GetScreenResourcesCurrent and CRTC change events
+        randr::query_version(conn, 1, 3).get_reply()?;
         Ok(())
     }
 
@@ -160,4 +208,16 @@ pub mod extensions {
 
         Ok(overlay.overlay_win())
     }
+
+    /// Undoes `redirect_subwindows`, for another compositor to take over
+    pub fn unredirect_subwindows(conn: &xcb::Connection) {
+        let setup = conn.get_setup();
+        let screen = setup.roots().last().unwrap();
+        composite::unredirect_subwindows(
+            conn,
+            screen.root(),
+            composite::REDIRECT_MANUAL as u8,
+        );
+        composite::release_overlay_window(conn, screen.root());
+    }
 }
@@ -0,0 +1,316 @@
+use crate::config::LutConfig;
+use crate::output::{self, Output};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 3D colour lookup table, read from an Adobe/Resolve `.cube` file
+pub struct Lut {
+    /// Number of entries along each axis
+    pub size: usize,
+    /// `size`³ RGB entries, red changing fastest, then green
+    pub table: Vec<[f32; 3]>,
+    /// Input colours mapped to the first and last entries
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+}
+
+impl Lut {
+    pub fn load(path: &Path) -> Result<Lut, String> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Lut::parse(&source)
+            .map_err(|(line, e)| format!("{}:{}: {}", path.display(), line, e))
+    }
+
+    /// Parses the contents of a `.cube` file, errors come with their line
+    pub fn parse(source: &str) -> Result<Lut, (usize, String)> {
+        let mut size = None;
+        // Entries of a 1D shaper LUT, which come before the 3D ones
+        let mut skipped = 0;
+        let mut table = Vec::new();
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut last_line = 0;
+        for (i, line) in source.lines().enumerate() {
+            let number = i + 1;
+            last_line = number;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, args) =
+                line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let error = |msg: &str| (number, msg.to_string());
+            match keyword {
+                "TITLE" => (),
+                "LUT_3D_SIZE" => {
+                    let n = args
+                        .trim()
+                        .parse::<usize>()
+                        .map_err(|_| error("invalid LUT_3D_SIZE"))?;
+                    if !(2..=256).contains(&n) {
+                        return Err(error("LUT_3D_SIZE must be 2 to 256"));
+                    }
+                    size = Some(n);
+                }
+                "LUT_1D_SIZE" => {
+                    skipped = args
+                        .trim()
+                        .parse::<usize>()
+                        .map_err(|_| error("invalid LUT_1D_SIZE"))?;
+                    warn!(
+                        "line {}: 1D LUTs are not supported, ignoring it",
+                        number
+                    );
+                }
+                "DOMAIN_MIN" => {
+                    domain_min = triple(args)
+                        .ok_or_else(|| error("DOMAIN_MIN expects 3 numbers"))?
+                }
+                "DOMAIN_MAX" => {
+                    domain_max = triple(args)
+                        .ok_or_else(|| error("DOMAIN_MAX expects 3 numbers"))?
+                }
+                // Resolve's way of writing the same domain on every axis
+                "LUT_3D_INPUT_RANGE" => {
+                    let range = args
+                        .split_whitespace()
+                        .map(str::parse::<f32>)
+                        .collect::<Result<Vec<_>, _>>();
+                    match range.as_deref() {
+                        Ok(&[min, max]) => {
+                            domain_min = [min; 3];
+                            domain_max = [max; 3];
+                        }
+                        _ => {
+                            return Err(error(
+                                "LUT_3D_INPUT_RANGE expects 2 numbers",
+                            ))
+                        }
+                    }
+                }
+                _ => match triple(line) {
+                    Some(_) if skipped > 0 => skipped -= 1,
+                    Some(entry) if size.is_some() => table.push(entry),
+                    Some(_) => {
+                        return Err(error("entries come after LUT_3D_SIZE"))
+                    }
+                    // Keywords of other tools, or of later versions
+                    None if keyword
+                        .starts_with(|c: char| c.is_ascii_uppercase()) =>
+                    {
+                        warn!(
+                            "line {}: ignoring unknown keyword `{}`",
+                            number, keyword
+                        );
+                    }
+                    None => {
+                        return Err(error(&format!(
+                            "unexpected `{}`",
+                            keyword
+                        )))
+                    }
+                },
+            }
+        }
+        let size = size.ok_or((last_line, "missing LUT_3D_SIZE".into()))?;
+        if table.len() != size * size * size {
+            return Err((
+                last_line,
+                format!(
+                    "expected {} entries, found {}",
+                    size * size * size,
+                    table.len()
+                ),
+            ));
+        }
+        if (0..3).any(|i| domain_max[i] <= domain_min[i]) {
+            return Err((
+                last_line,
+                "DOMAIN_MAX must exceed DOMAIN_MIN".into(),
+            ));
+        }
+        Ok(Lut { size, table, domain_min, domain_max })
+    }
+
+    /// Corrected colour of `rgb`, interpolated between the 8 closest
+    /// entries
+    pub fn sample(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let n = self.size;
+        let mut base = [0; 3];
+        let mut frac = [0.0; 3];
+        for i in 0..3 {
+            let range = self.domain_max[i] - self.domain_min[i];
+            let t = ((rgb[i] - self.domain_min[i]) / range).clamp(0.0, 1.0)
+                * (n - 1) as f32;
+            base[i] = (t as usize).min(n - 2);
+            frac[i] = t - base[i] as f32;
+        }
+        let mut out = [0.0; 3];
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let mut weight = 1.0;
+            for i in 0..3 {
+                weight *= if offset[i] == 1 { frac[i] } else { 1.0 - frac[i] };
+            }
+            let [r, g, b] = [0, 1, 2].map(|i| base[i] + offset[i]);
+            let entry = self.table[r + g * n + b * n * n];
+            for i in 0..3 {
+                out[i] += weight * entry[i];
+            }
+        }
+        out
+    }
+}
+
+/// Three numbers separated by whitespace
+fn triple(s: &str) -> Option<[f32; 3]> {
+    let mut values = s.split_whitespace().map(str::parse::<f32>);
+    let triple =
+        [values.next()?.ok()?, values.next()?.ok()?, values.next()?.ok()?];
+    values.next().is_none().then_some(triple)
+}
+
+/// LUT along with the parts of the screen it corrects
+pub struct Correction {
+    pub lut: Lut,
+    /// [x, y, width, height] on the root window
+    pub rects: Vec<[i32; 4]>,
+}
+
+/// Loads the LUTs of `config` for the current outputs of `root`, which
+/// is `screen` sized
+pub fn load(
+    conn: &xcb::Connection,
+    root: xcb::Window,
+    config: &LutConfig,
+    screen: [u16; 2],
+) -> Vec<Correction> {
+    if config.file.is_none() && config.outputs.is_empty() {
+        return Vec::new();
+    }
+    corrections(config, &output::outputs(conn, root), screen)
+}
+
+/// Loads the LUTs of `config` for `outputs`.
+///
+/// Outputs without a LUT of their own use the global one, which covers
+/// the whole screen when no output could be found. Files that fail to
+/// load are reported and left out.
+fn corrections(
+    config: &LutConfig,
+    outputs: &[Output],
+    screen: [u16; 2],
+) -> Vec<Correction> {
+    for name in config.outputs.keys() {
+        if !outputs.iter().any(|output| &output.name == name) {
+            warn!("No enabled output named {} for its LUT", name);
+        }
+    }
+    let mut areas: BTreeMap<&PathBuf, Vec<[i32; 4]>> = BTreeMap::new();
+    for output in outputs {
+        if let Some(path) =
+            config.outputs.get(&output.name).or(config.file.as_ref())
+        {
+            let rects = areas.entry(path).or_default();
+            // Mirrored outputs show the same area
+            if !rects.contains(&output.rect) {
+                rects.push(output.rect);
+            }
+        }
+    }
+    if let (Some(path), true) = (&config.file, outputs.is_empty()) {
+        areas.insert(path, vec![[0, 0, screen[0] as i32, screen[1] as i32]]);
+    }
+    areas
+        .into_iter()
+        .filter_map(|(path, rects)| match Lut::load(path) {
+            Ok(lut) => Some(Correction { lut, rects }),
+            Err(e) => {
+                error!("Unable to load LUT: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2x2x2 LUT leaving colours as they are
+    const IDENTITY: &str = "\
+TITLE \"identity\"
+LUT_3D_SIZE 2
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1
+";
+
+    #[test]
+    fn parses_cube_files() {
+        let lut = Lut::parse(IDENTITY).unwrap();
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.table.len(), 8);
+        assert_eq!(lut.table[1], [1.0, 0.0, 0.0]);
+        assert_eq!(lut.domain_min, [0.0; 3]);
+        assert_eq!(lut.domain_max, [1.0; 3]);
+    }
+
+    #[test]
+    fn reads_domains() {
+        let source =
+            format!("DOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\n{}", IDENTITY);
+        assert_eq!(Lut::parse(&source).unwrap().domain_max, [2.0; 3]);
+        let source = format!("LUT_3D_INPUT_RANGE -1 1\n{}", IDENTITY);
+        let lut = Lut::parse(&source).unwrap();
+        assert_eq!(lut.domain_min, [-1.0; 3]);
+        assert_eq!(lut.domain_max, [1.0; 3]);
+    }
+
+    #[test]
+    fn skips_unknown_keywords() {
+        let header = "LUT_IN_VIDEO_RANGE\nLUT_1D_SIZE 2\nLUT_3D_SIZE 2\n";
+        // The 1D entries come first and are left out
+        let shaper = "0.5 0.5 0.5\n0.5 0.5 0.5\n";
+        let entries = IDENTITY.split_once("LUT_3D_SIZE 2\n").unwrap().1;
+        let source = format!("{}{}{}", header, shaper, entries);
+        let lut = Lut::parse(&source).unwrap();
+        assert_eq!(lut.table, Lut::parse(IDENTITY).unwrap().table);
+        assert!(Lut::parse(&format!("lowercase\n{}", IDENTITY)).is_err());
+    }
+
+    #[test]
+    fn reports_errors_with_their_line() {
+        let missing = IDENTITY.replace("1 1 1\n", "");
+        assert_eq!(
+            Lut::parse(&missing).err().unwrap(),
+            (9, "expected 8 entries, found 7".to_string())
+        );
+        let early = "0 0 0\nLUT_3D_SIZE 2\n";
+        assert_eq!(Lut::parse(early).err().unwrap().0, 1);
+        let bad = IDENTITY.replace("1 0 0", "1 0 zero");
+        assert_eq!(Lut::parse(&bad).err().unwrap().0, 4);
+        let size = IDENTITY.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 1");
+        assert_eq!(Lut::parse(&size).err().unwrap().0, 2);
+    }
+
+    #[test]
+    fn interpolates_between_entries() {
+        let lut = Lut::parse(IDENTITY).unwrap();
+        assert_eq!(lut.sample([0.25, 0.5, 1.0]), [0.25, 0.5, 1.0]);
+        // Out of the domain, the closest entry
+        assert_eq!(lut.sample([-1.0, 2.0, 0.0]), [0.0, 1.0, 0.0]);
+        let inverted = Lut {
+            table: lut.table.iter().map(|c| c.map(|v| 1.0 - v)).collect(),
+            ..lut
+        };
+        assert_eq!(inverted.sample([0.25, 0.5, 1.0]), [0.75, 0.5, 0.0]);
+    }
+}
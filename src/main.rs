@@ -1,29 +1,153 @@
 extern crate xcb;
 
+#[macro_use]
+mod log;
+
+mod anim;
+mod atoms;
 mod backend;
+mod cli;
+mod config;
+mod corners;
+mod diagnose;
 mod event;
+mod filter;
 mod init;
+mod lut;
+mod night;
+mod output;
+mod poll;
+mod region;
+mod reload;
+mod rules;
+mod schedule;
+mod shadow;
 mod state;
 mod window;
 
-use backend::opengl;
+use anim::Clock;
+use backend::{headless, opengl, xrender, Backend};
+use cli::Options;
+use config::{BackendKind, Config, ConfigError};
+use event::PendingDamage;
+use poll::{Poller, Wakeup};
+use schedule::Scheduler;
 use state::State;
+use std::env;
 use std::process;
 use window::Window;
 
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 fn main() {
-    let mut state = State::init().unwrap();
+    let options = Options::parse(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("rcomp: {}\n\n{}", err, cli::USAGE);
+        process::exit(2);
+    });
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    if options.version {
+        println!("rcomp {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+
+    let config = load_config(&options).unwrap_or_else(|err| {
+        eprintln!("Invalid config: {}", err);
+        process::exit(1);
+    });
+    log::set_level(config.log_level);
+    if options.print_config {
+        print!("{}", toml::to_string(&config).unwrap());
+        return;
+    }
+
+    if let Some(display) = &options.display {
+        env::set_var("DISPLAY", display);
+    }
+    if options.diagnose {
+        let (conn, xlib_screens) = xcb::Connection::connect_with_xlib_display(
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("Unable to connect to the X server: {}", err);
+            process::exit(1);
+        });
+        diagnose::print(&conn, xlib_screens);
+        return;
+    }
+
+    // Before the backend starts threads of its own
+    poll::block_signals();
+    let mut state = State::init(options.replace).unwrap_or_else(|err| {
+        eprintln!("Unable to connect to the X server: {}", err);
+        process::exit(1);
+    });
     state.update_root_pixmap();
 
-    let mut windows = Window::fetch_windows(&state.conn);
-    init::window::request_events(&state.conn);
+    match config.backend {
+        BackendKind::Headless => {
+            let backend =
+                init_or_exit(headless::Headless::init(&state, &config));
+            run(&state, &options, config, backend);
+        }
+        BackendKind::Xrender => {
+            let backend =
+                init_or_exit(xrender::XRender::init(&state, &config));
+            run(&state, &options, config, backend);
+        }
+        BackendKind::Opengl => match opengl::Opengl::init(&state, &config) {
+            Ok(backend) => run(&state, &options, config, backend),
+            Err(err) => {
+                warn!("Unable to initialize OpenGL backend: {}", err);
+                warn!("Falling back to XRender backend");
+                let backend =
+                    init_or_exit(xrender::XRender::init(&state, &config));
+                run(&state, &options, config, backend);
+            }
+        },
+    };
+}
 
-    let backend = opengl::Opengl::init(&state).unwrap_or_else(|err| {
+/// Exits if the backend could not be initialized
+fn init_or_exit<B>(backend: Result<B, &str>) -> B {
+    backend.unwrap_or_else(|err| {
         eprintln!("Unable to initialize backend: {}", err);
         process::exit(1);
-    });
+    })
+}
+
+/// Loads the config file and applies the command line overrides
+fn load_config(options: &Options) -> Result<Config, ConfigError> {
+    let mut config = Config::load(options.config.as_deref())?;
+    options.apply(&mut config);
+    Ok(config)
+}
+
+/// Runs the event loop using the given backend
+fn run<B: Backend>(
+    state: &State,
+    options: &Options,
+    mut config: Config,
+    mut backend: B,
+) {
+    let config_path = options.config.clone().or_else(config::default_path);
+    let poller = Poller::new(&state.conn, config_path.as_deref())
+        .unwrap_or_else(|err| {
+            eprintln!("Unable to set up the event loop: {}", err);
+            process::exit(1);
+        });
+
+    let mut windows: Vec<Window<B::Context>> =
+        Window::fetch_windows(&state.conn, &state.atoms);
+    for win in windows.iter_mut() {
+        rules::apply(&config.rules, win);
+    }
+    let active =
+        window::fetch_active(&state.conn, &state.atoms, state.root.id);
+    window::set_focus(&mut windows, active);
+    init::window::request_events(&state.conn);
+    backend.set_color_filters(&filter::global(state, &config));
 
     // initial render
     for win in windows.iter_mut().filter(|w| w.mapped) {
@@ -32,26 +156,128 @@ fn main() {
         backend.update_pos(win);
         backend.update_pixmap(win);
         backend.update_texture(win);
-        backend.draw_window(win);
+        backend.update_masks(win);
     }
-    backend.render();
-    let mut last_render = Instant::now();
-    // 60 FPS = 16.666 ms
-    let update_frequency = Duration::from_millis(17);
+    let mut scheduler = Scheduler::new(schedule::frame_interval(
+        &state.conn,
+        state.root.id,
+        &config,
+    ));
+    scheduler.request_full();
 
+    let mut clock = Clock::new();
+    let mut shifting = false;
+    // Event read along with a reply, the connection not being readable
+    // because of it
+    let mut queued = None;
+    let mut pending_damage = PendingDamage::default();
     loop {
-        match state.conn.wait_for_event() {
-            None => break,
-            Some(event) => {
+        // Outputs change in bursts of events, handled once they are all in
+        let mut outputs_changed = false;
+        // Handle every queued event first, so that they all make it in the
+        // next frame
+        while let Some(event) =
+            queued.take().or_else(|| state.conn.poll_for_event())
+        {
+            if filter::is_toggle(state, &event) {
+                backend.set_color_filters(&filter::global(state, &config));
+                scheduler.request_full();
+            } else if output::is_change(state, &event) {
+                outputs_changed = true;
+            } else {
                 event::handle_event(
-                    &state,
+                    state,
                     event,
                     &mut windows,
                     &backend,
-                    &mut last_render,
-                    &update_frequency,
+                    &config,
+                    &mut scheduler,
+                    &mut pending_damage,
                 );
             }
+            // Events that keep coming must not hold the frame back, the
+            // rest are handled after it
+            if scheduler.timeout() == Some(Duration::ZERO) {
+                break;
+            }
+        }
+        if state.conn.has_error().is_err() {
+            break;
+        }
+        pending_damage.fetch(state, &windows, &mut scheduler);
+        if outputs_changed {
+            backend.update_outputs(&config);
+            scheduler.set_interval(schedule::frame_interval(
+                &state.conn,
+                state.root.id,
+                &config,
+            ));
+            scheduler.request_full();
+        }
+
+        let animating = windows.iter().any(|w| w.animating());
+        let was_shifting = shifting;
+        shifting = night::changing(&config.night_light, night::local_time());
+        if was_shifting && !shifting {
+            // Lands on the final temperature
+            scheduler.request_full();
+        }
+        let stepping = animating || shifting;
+        if stepping {
+            let interval = if animating {
+                // At least once a frame, more often if fades ask for it
+                Duration::from_millis(config.fade.step)
+                    .min(scheduler.interval())
+            } else {
+                Duration::from_millis(night::STEP)
+            };
+            if clock.tick(interval) {
+                event::step_animations(&mut windows, &backend);
+                scheduler.request_full();
+            }
+        }
+        if event::rebuild_resized_masks(&mut windows, &backend) {
+            scheduler.request_full();
+        }
+        if let Some(damage) = scheduler.take_due() {
+            event::draw_frame(&windows, &backend, damage);
+        }
+
+        queued = state.conn.poll_for_queued_event();
+        if queued.is_some() {
+            continue;
+        }
+
+        // Sleep until something happens, at most until the pending frame,
+        // the next animation step or the end of a resize
+        let timeout = [
+            scheduler.timeout(),
+            stepping.then(|| clock.timeout()),
+            event::resize_timeout(&windows),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        poller.set_timer(timeout);
+        state.conn.flush();
+        let wakeups = poller.wait();
+        if wakeups.contains(&Wakeup::Quit) {
+            break;
+        }
+        if wakeups.contains(&Wakeup::Reload) {
+            reload::apply(
+                load_config(options),
+                &mut config,
+                &mut windows,
+                &mut backend,
+            );
+            backend.set_color_filters(&filter::global(state, &config));
+            scheduler.set_interval(schedule::frame_interval(
+                &state.conn,
+                state.root.id,
+                &config,
+            ));
+            scheduler.request_full();
         }
     }
 }
@@ -0,0 +1,226 @@
+use crate::config::NightLightConfig;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const DAY: u32 = 24 * 60 * 60;
+
+/// Redraw interval while the temperature shifts, in milliseconds
+pub const STEP: u64 = 1000;
+
+/// Local time of day, written as `"HH:MM"`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay {
+    /// Seconds since midnight
+    pub seconds: u32,
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<TimeOfDay, String> {
+        let invalid =
+            || format!("invalid time `{}`, expected \"HH:MM\"", value);
+        let (hours, minutes) =
+            value.trim().split_once(':').ok_or_else(invalid)?;
+        let hours = hours.parse::<u32>().map_err(|_| invalid())?;
+        let minutes = minutes.parse::<u32>().map_err(|_| invalid())?;
+        if hours > 23 || minutes > 59 {
+            return Err(invalid());
+        }
+        Ok(TimeOfDay { seconds: hours * 3600 + minutes * 60 })
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.seconds / 3600, self.seconds / 60 % 60)
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> String {
+        time.to_string()
+    }
+}
+
+/// Seconds since local midnight, following `TZ`
+pub fn local_time() -> u32 {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        libc::localtime_r(&now, &mut tm);
+    }
+    (tm.tm_hour * 3600 + tm.tm_min * 60 + tm.tm_sec) as u32
+}
+
+/// Colour of white frames get multiplied by at `time`, in seconds since
+/// midnight. `None` when the night light is off.
+pub fn current(config: &NightLightConfig, time: u32) -> Option<[f32; 3]> {
+    config.enabled.then(|| white_point(temperature(config, time)))
+}
+
+/// Colour temperature at `time`, in seconds since midnight.
+///
+/// It starts shifting at sunset and sunrise, reaching the night or day
+/// temperature `transition` minutes later. Without a schedule, it is
+/// always night.
+pub fn temperature(config: &NightLightConfig, time: u32) -> f32 {
+    let day = config.day_temperature as f32;
+    let night = config.temperature as f32;
+    day + (night - day) * darkness(config, time)
+}
+
+/// Whether the temperature is shifting at `time`, frames then have to be
+/// drawn even when nothing else changes
+pub fn changing(config: &NightLightConfig, time: u32) -> bool {
+    let (sunset, sunrise) =
+        match (config.enabled, config.sunset, config.sunrise) {
+            (true, Some(sunset), Some(sunrise)) => (sunset, sunrise),
+            _ => return false,
+        };
+    let transition = config.transition * 60;
+    since(sunset, time) < transition || since(sunrise, time) < transition
+}
+
+/// How far into the night `time` is, from 0 during the day to 1
+fn darkness(config: &NightLightConfig, time: u32) -> f32 {
+    let (sunset, sunrise) = match (config.sunset, config.sunrise) {
+        (Some(sunset), Some(sunrise)) => (sunset, sunrise),
+        _ => return 1.0,
+    };
+    let transition = (config.transition * 60) as f32;
+    let progress = |elapsed: u32| {
+        if transition == 0.0 {
+            1.0
+        } else {
+            (elapsed as f32 / transition).min(1.0)
+        }
+    };
+    let (after_sunset, after_sunrise) =
+        (since(sunset, time), since(sunrise, time));
+    // Whichever happened last decides, transitions being shorter than
+    // both the day and the night
+    if after_sunset < after_sunrise {
+        progress(after_sunset)
+    } else {
+        1.0 - progress(after_sunrise)
+    }
+}
+
+/// Seconds from `start` to `time`, going over midnight
+fn since(start: TimeOfDay, time: u32) -> u32 {
+    (time % DAY + DAY - start.seconds) % DAY
+}
+
+/// RGB multipliers turning white into the colour of a black body at
+/// `kelvin`, 6500K leaving it as is.
+///
+/// Uses Tanner Helland's fit of the black body colours, good from 1000K
+/// to 40000K.
+pub fn white_point(kelvin: f32) -> [f32; 3] {
+    let reference = black_body(6500.0);
+    let color = black_body(kelvin);
+    [0, 1, 2].map(|i| (color[i] / reference[i]).min(1.0))
+}
+
+fn black_body(kelvin: f32) -> [f32; 3] {
+    let t = kelvin / 100.0;
+    let red = if t <= 66.0 {
+        255.0
+    } else {
+        329.69873 * (t - 60.0).powf(-0.13320476)
+    };
+    let green = if t <= 66.0 {
+        99.4708 * t.ln() - 161.11957
+    } else {
+        288.12216 * (t - 60.0).powf(-0.07551485)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.51773 * (t - 10.0).ln() - 305.0448
+    };
+    [red, green, blue].map(|c| c.clamp(0.0, 255.0) / 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hours: u32, minutes: u32) -> u32 {
+        hours * 3600 + minutes * 60
+    }
+
+    fn schedule(sunset: u32, sunrise: u32) -> NightLightConfig {
+        NightLightConfig {
+            enabled: true,
+            sunset: Some(TimeOfDay { seconds: sunset }),
+            sunrise: Some(TimeOfDay { seconds: sunrise }),
+            ..NightLightConfig::default()
+        }
+    }
+
+    #[test]
+    fn keeps_day_and_night_temperatures() {
+        let config = schedule(at(20, 0), at(6, 0));
+        assert_eq!(temperature(&config, at(12, 0)), 6500.0);
+        assert_eq!(temperature(&config, at(23, 0)), 4500.0);
+        assert_eq!(temperature(&config, at(3, 0)), 4500.0);
+        assert!(!changing(&config, at(12, 0)));
+        assert!(!changing(&config, at(23, 0)));
+    }
+
+    #[test]
+    fn shifts_after_sunset_and_sunrise() {
+        let config = schedule(at(20, 0), at(6, 0));
+        assert_eq!(darkness(&config, at(20, 0)), 0.0);
+        assert_eq!(darkness(&config, at(20, 15)), 0.5);
+        assert_eq!(darkness(&config, at(20, 30)), 1.0);
+        assert_eq!(darkness(&config, at(6, 15)), 0.5);
+        assert_eq!(darkness(&config, at(6, 30)), 0.0);
+        assert_eq!(temperature(&config, at(20, 15)), 5500.0);
+        assert!(changing(&config, at(20, 0)));
+        assert!(changing(&config, at(6, 29)));
+        assert!(!changing(&config, at(6, 30)));
+    }
+
+    #[test]
+    fn shifts_over_midnight() {
+        let config = schedule(at(23, 45), at(7, 0));
+        assert_eq!(darkness(&config, at(0, 0)), 0.5);
+        assert!(changing(&config, at(0, 0)));
+        assert!(!changing(&config, at(0, 15)));
+        assert_eq!(darkness(&config, at(0, 15)), 1.0);
+        // Past midnight, time wraps around
+        assert_eq!(darkness(&config, DAY + at(0, 15)), 1.0);
+    }
+
+    #[test]
+    fn stays_at_night_without_schedule() {
+        let config =
+            NightLightConfig { enabled: true, ..NightLightConfig::default() };
+        assert_eq!(darkness(&config, at(12, 0)), 1.0);
+        assert!(!changing(&config, at(12, 0)));
+        let disabled = NightLightConfig {
+            enabled: false,
+            ..schedule(at(20, 0), at(6, 0))
+        };
+        assert!(!changing(&disabled, at(20, 0)));
+        assert_eq!(current(&disabled, at(23, 0)), None);
+    }
+
+    #[test]
+    fn follows_black_body_colours() {
+        assert_eq!(white_point(6500.0), [1.0, 1.0, 1.0]);
+        let [red, green, blue] = white_point(3000.0);
+        assert_eq!(red, 1.0);
+        assert!(blue < green && green < 1.0);
+        assert_eq!(black_body(1500.0)[2], 0.0);
+        let [red, _, blue] = black_body(10_000.0);
+        assert!(red < 1.0);
+        assert_eq!(blue, 1.0);
+    }
+}
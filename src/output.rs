@@ -0,0 +1,116 @@
+use crate::state::State;
+use xcb::randr;
+
+/// RandR output currently showing part of the screen
+pub struct Output {
+    pub name: String,
+    /// [x, y, width, height] on the root window
+    pub rect: [i32; 4],
+    /// Refresh rate of its mode, in Hz, 0 when unknown
+    pub refresh: f64,
+}
+
+/// Outputs that are connected and enabled
+pub fn outputs(conn: &xcb::Connection, root: xcb::Window) -> Vec<Output> {
+    let resources =
+        match randr::get_screen_resources_current(conn, root).get_reply() {
+            Ok(resources) => resources,
+            Err(_) => {
+                warn!("Unable to query RandR outputs");
+                return Vec::new();
+            }
+        };
+    let timestamp = resources.config_timestamp();
+    resources
+        .outputs()
+        .iter()
+        .filter_map(|&output| {
+            let info =
+                randr::get_output_info(conn, output, timestamp).get_reply();
+            let info = info.ok().filter(|info| info.crtc() != xcb::NONE)?;
+            let crtc = randr::get_crtc_info(conn, info.crtc(), timestamp)
+                .get_reply()
+                .ok()?;
+            let refresh = resources
+                .modes()
+                .find(|mode| mode.id() == crtc.mode())
+                .map_or(0.0, |mode| refresh_rate(&mode));
+            Some(Output {
+                name: String::from_utf8_lossy(info.name()).into_owned(),
+                rect: [
+                    crtc.x() as i32,
+                    crtc.y() as i32,
+                    crtc.width() as i32,
+                    crtc.height() as i32,
+                ],
+                refresh,
+            })
+        })
+        .collect()
+}
+
+/// Whether `event` tells that outputs were enabled, disabled, moved or
+/// changed modes
+pub fn is_change(state: &State, event: &xcb::GenericEvent) -> bool {
+    let response_type = event.response_type() & !0x80;
+    if response_type == state.randr_event + randr::SCREEN_CHANGE_NOTIFY {
+        return true;
+    }
+    if response_type != state.randr_event + randr::NOTIFY {
+        return false;
+    }
+    let ev: &randr::NotifyEvent = unsafe { xcb::cast_event(event) };
+    ev.sub_code() == randr::NOTIFY_CRTC_CHANGE as u8
+}
+
+/// Vertical refresh rate of a mode, in Hz
+fn refresh_rate(mode: &randr::ModeInfo) -> f64 {
+    let mut lines = mode.vtotal() as f64;
+    let flags = mode.mode_flags();
+    if flags & randr::MODE_FLAG_DOUBLE_SCAN != 0 {
+        lines *= 2.0;
+    }
+    // Every frame only draws half of the lines
+    if flags & randr::MODE_FLAG_INTERLACE != 0 {
+        lines /= 2.0;
+    }
+    let pixels = mode.htotal() as f64 * lines;
+    if pixels == 0.0 {
+        return 0.0;
+    }
+    mode.dot_clock() as f64 / pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode(dot_clock: u32, htotal: u16, vtotal: u16, flags: u32) -> f64 {
+        let mode = randr::ModeInfo::new(
+            0, 0, 0, dot_clock, 0, 0, htotal, 0, 0, 0, vtotal, 0, flags,
+        );
+        refresh_rate(&mode)
+    }
+
+    #[test]
+    fn computes_refresh_rates() {
+        // CEA 1080p60
+        assert_eq!(mode(148_500_000, 2200, 1125, 0), 60.0);
+        let rate = mode(241_500_000, 2720, 1481, 0);
+        assert!((rate - 59.95).abs() < 0.01, "{}", rate);
+    }
+
+    #[test]
+    fn follows_scan_flags() {
+        // CEA 1080i60 draws half of its lines every field
+        let interlaced = randr::MODE_FLAG_INTERLACE;
+        assert_eq!(mode(74_250_000, 2200, 1125, interlaced), 60.0);
+        let double_scan = randr::MODE_FLAG_DOUBLE_SCAN;
+        assert_eq!(mode(148_500_000, 2200, 1125, double_scan), 30.0);
+    }
+
+    #[test]
+    fn ignores_modes_without_timings() {
+        assert_eq!(mode(148_500_000, 0, 0, 0), 0.0);
+    }
+}
@@ -0,0 +1,257 @@
+use crate::reload::FileWatcher;
+use std::io;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::Duration;
+
+/// Why the event loop woke up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wakeup {
+    /// The X connection has data to read
+    X,
+    /// The timer set with `set_timer` expired
+    Timer,
+    /// SIGHUP was received or the config file changed
+    Reload,
+    /// SIGINT or SIGTERM was received
+    Quit,
+}
+
+// Identify the sources in epoll events
+const X: u64 = 0;
+const TIMER: u64 = 1;
+const SIGNAL: u64 = 2;
+const CONFIG: u64 = 3;
+
+/// Sources the event loop waits on, all at once through epoll: the X
+/// connection, a timer for the next frame or animation step, signals and
+/// the config file.
+pub struct Poller {
+    epoll: i32,
+    timer: i32,
+    signal: i32,
+    config: Option<FileWatcher>,
+}
+
+/// Blocks the signals handled by the event loop, which then only get
+/// them through its signalfd. Has to be done before any other thread is
+/// started, since they inherit the mask.
+pub fn block_signals() {
+    let mask = signal_mask();
+    unsafe {
+        libc::pthread_sigmask(libc::SIG_BLOCK, &mask, std::ptr::null_mut())
+    };
+}
+
+fn signal_mask() -> libc::sigset_t {
+    unsafe {
+        let mut mask: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut mask);
+        for signal in [libc::SIGHUP, libc::SIGINT, libc::SIGTERM] {
+            libc::sigaddset(&mut mask, signal);
+        }
+        mask
+    }
+}
+
+/// Turns the -1 returned by failing calls into the error
+fn check(fd: i32) -> io::Result<i32> {
+    if fd < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(fd)
+    }
+}
+
+/// One-shot timer setting expiring after `timeout`, disarming it when
+/// `None`
+fn timer_spec(timeout: Option<Duration>) -> libc::itimerspec {
+    // Zero stops the timer, expire as soon as possible instead
+    let value = timeout.map_or(Duration::ZERO, |timeout| {
+        timeout.max(Duration::from_nanos(1))
+    });
+    libc::itimerspec {
+        it_interval: libc::timespec { tv_sec: 0, tv_nsec: 0 },
+        it_value: libc::timespec {
+            tv_sec: value.as_secs() as libc::time_t,
+            tv_nsec: value.subsec_nanos() as libc::c_long,
+        },
+    }
+}
+
+impl Poller {
+    /// Also watches `config_path` for changes when given, the config
+    /// still being reloaded on SIGHUP if that fails
+    pub fn new(
+        conn: &xcb::Connection,
+        config_path: Option<&Path>,
+    ) -> io::Result<Poller> {
+        // Filled in one by one, so that Drop closes what was opened
+        let mut poller =
+            Poller { epoll: -1, timer: -1, signal: -1, config: None };
+        poller.epoll =
+            check(unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) })?;
+        poller.timer = check(unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_CLOEXEC | libc::TFD_NONBLOCK,
+            )
+        })?;
+        poller.signal = check(unsafe {
+            libc::signalfd(
+                -1,
+                &signal_mask(),
+                libc::SFD_CLOEXEC | libc::SFD_NONBLOCK,
+            )
+        })?;
+        poller.config = config_path.and_then(|path| {
+            FileWatcher::new(path)
+                .map_err(|e| {
+                    info!("Not watching {} for changes: {}", path.display(), e)
+                })
+                .ok()
+        });
+        poller.add(conn.as_raw_fd(), X)?;
+        poller.add(poller.timer, TIMER)?;
+        poller.add(poller.signal, SIGNAL)?;
+        if let Some(watcher) = &poller.config {
+            poller.add(watcher.fd(), CONFIG)?;
+        }
+        Ok(poller)
+    }
+
+    fn add(&self, fd: i32, source: u64) -> io::Result<()> {
+        let mut event =
+            libc::epoll_event { events: libc::EPOLLIN as u32, u64: source };
+        check(unsafe {
+            libc::epoll_ctl(self.epoll, libc::EPOLL_CTL_ADD, fd, &mut event)
+        })?;
+        Ok(())
+    }
+
+    /// Makes the timer expire once `timeout` has passed, `None` stops it
+    pub fn set_timer(&self, timeout: Option<Duration>) {
+        let spec = timer_spec(timeout);
+        if let Err(e) = check(unsafe {
+            libc::timerfd_settime(self.timer, 0, &spec, std::ptr::null_mut())
+        }) {
+            warn!("Unable to set the frame timer: {}", e);
+        }
+    }
+
+    /// Blocks until at least one source is ready. Nothing is returned
+    /// when interrupted, or when the only change was to files other than
+    /// the config one.
+    pub fn wait(&self) -> Vec<Wakeup> {
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; 4];
+        let count = unsafe {
+            libc::epoll_wait(
+                self.epoll,
+                events.as_mut_ptr(),
+                events.len() as i32,
+                -1,
+            )
+        };
+        if count < 0 {
+            return Vec::new();
+        }
+        events[..count as usize]
+            .iter()
+            .filter_map(|event| match event.u64 {
+                X => Some(Wakeup::X),
+                TIMER => {
+                    // Expiration count, read to rearm
+                    let mut expirations = 0u64;
+                    let len = unsafe {
+                        libc::read(
+                            self.timer,
+                            &mut expirations as *mut u64 as *mut libc::c_void,
+                            mem::size_of::<u64>(),
+                        )
+                    };
+                    // Nothing is left to read when it was set again since
+                    let error = io::Error::last_os_error();
+                    if len < 0 && error.kind() != io::ErrorKind::WouldBlock {
+                        warn!("Unable to read the frame timer: {}", error);
+                    }
+                    Some(Wakeup::Timer)
+                }
+                SIGNAL => self.read_signals(),
+                _ => {
+                    let watcher = self.config.as_ref()?;
+                    watcher.changed().then(|| {
+                        info!("Config file changed, reloading");
+                        Wakeup::Reload
+                    })
+                }
+            })
+            .collect()
+    }
+
+    /// Reads every pending signal, quitting taking precedence
+    fn read_signals(&self) -> Option<Wakeup> {
+        let mut wakeup = None;
+        loop {
+            let mut info: libc::signalfd_siginfo = unsafe { mem::zeroed() };
+            let len = unsafe {
+                libc::read(
+                    self.signal,
+                    &mut info as *mut _ as *mut libc::c_void,
+                    mem::size_of::<libc::signalfd_siginfo>(),
+                )
+            };
+            if len <= 0 {
+                return wakeup;
+            }
+            if info.ssi_signo == libc::SIGHUP as u32 {
+                info!("Got SIGHUP, reloading config");
+                wakeup = wakeup.or(Some(Wakeup::Reload));
+            } else {
+                info!("Got signal {}, exiting", info.ssi_signo);
+                wakeup = Some(Wakeup::Quit);
+            }
+        }
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        for fd in [self.epoll, self.timer, self.signal] {
+            if fd >= 0 {
+                unsafe { libc::close(fd) };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(timeout: Option<Duration>) -> (libc::time_t, libc::c_long) {
+        let spec = timer_spec(timeout);
+        assert_eq!(
+            (spec.it_interval.tv_sec, spec.it_interval.tv_nsec),
+            (0, 0)
+        );
+        (spec.it_value.tv_sec, spec.it_value.tv_nsec)
+    }
+
+    #[test]
+    fn splits_timeouts() {
+        assert_eq!(value(Some(Duration::from_millis(16))), (0, 16_000_000));
+        assert_eq!(value(Some(Duration::from_millis(2500))), (2, 500_000_000));
+    }
+
+    #[test]
+    fn expires_due_timers_at_once() {
+        // A zero value would disarm the timer instead
+        assert_eq!(value(Some(Duration::ZERO)), (0, 1));
+    }
+
+    #[test]
+    fn disarms_without_timeout() {
+        assert_eq!(value(None), (0, 0));
+    }
+}
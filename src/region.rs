@@ -0,0 +1,137 @@
+/// Part of the screen to repaint
+#[derive(Debug, Clone, PartialEq)]
+pub enum Region {
+    Full,
+    /// [x, y, width, height] rects on the screen area, not overlapping
+    Rects(Vec<[i32; 4]>),
+}
+
+/// Most rects a region keeps apart, beyond which they are merged into
+/// their bounds
+const MAX_RECTS: usize = 16;
+
+impl Region {
+    /// Region covering both `self` and `other`
+    pub fn union(self, other: Region) -> Region {
+        match (self, other) {
+            (Region::Rects(mut rects), Region::Rects(other)) => {
+                for rect in other {
+                    insert(&mut rects, rect);
+                }
+                Region::Rects(rects)
+            }
+            _ => Region::Full,
+        }
+    }
+}
+
+/// Adds `rect` to the non overlapping `rects`, merging it with those it
+/// overlaps into their bounds
+pub fn insert(rects: &mut Vec<[i32; 4]>, mut rect: [i32; 4]) {
+    if rect[2] <= 0 || rect[3] <= 0 {
+        return;
+    }
+    if rects.iter().any(|&r| intersection(r, rect) == Some(rect)) {
+        return;
+    }
+    // Growing the rect can make it overlap rects it did not
+    while let Some(i) =
+        rects.iter().position(|&r| intersection(r, rect).is_some())
+    {
+        rect = union(rects.swap_remove(i), rect);
+    }
+    rects.push(rect);
+    if rects.len() > MAX_RECTS {
+        let bounds = rects.iter().copied().reduce(union).unwrap();
+        *rects = vec![bounds];
+    }
+}
+
+/// Smallest rect holding both `a` and `b`
+pub fn union(a: [i32; 4], b: [i32; 4]) -> [i32; 4] {
+    let x = a[0].min(b[0]);
+    let y = a[1].min(b[1]);
+    let right = (a[0] + a[2]).max(b[0] + b[2]);
+    let bottom = (a[1] + a[3]).max(b[1] + b[3]);
+    [x, y, right - x, bottom - y]
+}
+
+/// Overlap of `a` and `b`, `None` when they don't touch
+pub fn intersection(a: [i32; 4], b: [i32; 4]) -> Option<[i32; 4]> {
+    let x = a[0].max(b[0]);
+    let y = a[1].max(b[1]);
+    let right = (a[0] + a[2]).min(b[0] + b[2]);
+    let bottom = (a[1] + a[3]).min(b[1] + b[3]);
+    (right > x && bottom > y).then_some([x, y, right - x, bottom - y])
+}
+
+/// `rect` grown by `margin` on every side
+pub fn expand(rect: [i32; 4], margin: i32) -> [i32; 4] {
+    let [x, y, width, height] = rect;
+    [x - margin, y - margin, width + margin * 2, height + margin * 2]
+}
+
+/// Smallest rect of whole pixels holding `rect`
+pub fn enclosing(rect: [f32; 4]) -> [i32; 4] {
+    let [x, y, width, height] = rect;
+    let (left, top) = (x.floor(), y.floor());
+    let right = (x + width).ceil();
+    let bottom = (y + height).ceil();
+    [left, top, right - left, bottom - top].map(|v| v as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_apart_rects_apart() {
+        let region = Region::Rects(vec![[0, 0, 10, 10]])
+            .union(Region::Rects(vec![[100, 100, 10, 10]]));
+        assert_eq!(
+            region,
+            Region::Rects(vec![[0, 0, 10, 10], [100, 100, 10, 10]])
+        );
+        let full = region.union(Region::Full);
+        assert_eq!(full, Region::Full);
+    }
+
+    #[test]
+    fn merges_overlapping_rects() {
+        let mut rects = vec![[0, 0, 10, 10], [20, 0, 10, 10]];
+        // Overlaps the first, then the second once merged with it
+        insert(&mut rects, [5, 5, 16, 2]);
+        assert_eq!(rects, [[0, 0, 30, 10]]);
+        // Already covered
+        insert(&mut rects, [2, 2, 4, 4]);
+        assert_eq!(rects, [[0, 0, 30, 10]]);
+        insert(&mut rects, [40, 0, 0, 10]);
+        assert_eq!(rects, [[0, 0, 30, 10]]);
+    }
+
+    #[test]
+    fn bounds_the_number_of_rects() {
+        let mut rects = Vec::new();
+        for i in 0..=MAX_RECTS as i32 {
+            insert(&mut rects, [i * 20, 0, 10, 10]);
+        }
+        assert_eq!(rects, [[0, 0, MAX_RECTS as i32 * 20 + 10, 10]]);
+    }
+
+    #[test]
+    fn intersects_rects() {
+        assert_eq!(
+            intersection([0, 0, 10, 10], [5, 5, 10, 10]),
+            Some([5, 5, 5, 5])
+        );
+        // Touching edges do not overlap
+        assert_eq!(intersection([0, 0, 10, 10], [10, 0, 10, 10]), None);
+        assert_eq!(union([0, 0, 10, 10], [20, 5, 5, 10]), [0, 0, 25, 15]);
+    }
+
+    #[test]
+    fn grows_rects() {
+        assert_eq!(expand([10, 10, 5, 5], 3), [7, 7, 11, 11]);
+        assert_eq!(enclosing([0.5, 1.0, 2.0, 1.5]), [0, 1, 3, 2]);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::backend::Backend;
+use crate::config::{Config, ConfigError};
+use crate::window::Window;
+use std::ffi::{CString, OsStr, OsString};
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// Config file watched for changes, through inotify
+pub struct FileWatcher {
+    fd: i32,
+    file_name: Option<OsString>,
+}
+
+impl FileWatcher {
+    pub fn new(path: &Path) -> io::Result<FileWatcher> {
+        Ok(FileWatcher {
+            fd: watch_file(path)?,
+            file_name: path.file_name().map(|name| name.to_os_string()),
+        })
+    }
+
+    /// Becomes readable when something changed in the directory
+    pub fn fd(&self) -> i32 {
+        self.fd
+    }
+
+    /// Reads the pending changes, returns true if the file is one of them
+    pub fn changed(&self) -> bool {
+        file_changed(self.fd, self.file_name.as_deref())
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// Replaces `config` with a newly loaded one and applies it to the
+/// backend and tracked windows.
+///
+/// If the new config cannot be loaded, the current one is kept.
+pub fn apply<B: Backend>(
+    new_config: Result<Config, ConfigError>,
+    config: &mut Config,
+    windows: &mut [Window<B::Context>],
+    backend: &mut B,
+) {
+    let new_config = match new_config {
+        Ok(new_config) => new_config,
+        Err(err) => {
+            error!("Keeping current config, new one is invalid: {}", err);
+            return;
+        }
+    };
+    if new_config.backend != config.backend
+        || new_config.opengl.context_version != config.opengl.context_version
+    {
+        warn!("Changing the backend or context version requires a restart");
+    }
+    crate::log::set_level(new_config.log_level);
+    backend.reconfigure(&new_config);
+    for win in windows.iter_mut() {
+        crate::rules::apply(&new_config.rules, win);
+        if win.mapped {
+            backend.update_texture(win);
+            backend.update_masks(win);
+        }
+    }
+    *config = new_config;
+    info!("Config reloaded");
+}
+
+/// Watches the directory of `path`, since editors often replace files
+/// instead of writing to them.
+fn watch_file(path: &Path) -> io::Result<i32> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let dir = CString::new(dir.as_os_str().as_bytes())?;
+    unsafe {
+        let fd = libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::inotify_add_watch(
+            fd,
+            dir.as_ptr(),
+            libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO,
+        ) < 0
+        {
+            let err = io::Error::last_os_error();
+            libc::close(fd);
+            return Err(err);
+        }
+        Ok(fd)
+    }
+}
+
+/// Reads the pending inotify events, returns true if one of them is
+/// about `file_name`
+fn file_changed(fd: i32, file_name: Option<&OsStr>) -> bool {
+    let mut buf = [0u8; 4096];
+    let len = unsafe {
+        libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+    };
+    if len <= 0 {
+        return false;
+    }
+    let header_len = mem::size_of::<libc::inotify_event>();
+    let mut changed = false;
+    let mut offset = 0;
+    while offset + header_len <= len as usize {
+        let event: libc::inotify_event = unsafe {
+            std::ptr::read_unaligned(buf[offset..].as_ptr() as *const _)
+        };
+        let name = &buf[offset + header_len..][..event.len as usize];
+        // The name is padded with NUL bytes
+        let name = name.split(|b| *b == 0).next().unwrap_or(&[]);
+        if Some(OsStr::from_bytes(name)) == file_name {
+            changed = true;
+        }
+        offset += header_len + event.len as usize;
+    }
+    changed
+}
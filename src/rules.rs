@@ -0,0 +1,606 @@
+use crate::filter::ColorFilter;
+use crate::window::Window;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+/// A `[[rules]]` entry of the config: settings applied to every window
+/// matching a condition.
+///
+/// ```toml
+/// [[rules]]
+/// match = 'class == "Firefox" && !override_redirect'
+/// set = { opacity = 0.9 }
+/// ```
+///
+/// Rules are applied in order, later ones override the settings of
+/// earlier ones.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(rename = "match")]
+    pub condition: Condition,
+    pub set: Effects,
+}
+
+/// Per-window settings, `None` means the global setting is used.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Effects {
+    /// Window opacity, between 0 and 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f32>,
+    /// Dim the window when it is not focused, see `Config::inactive_dim`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dim: Option<bool>,
+    /// Draw a shadow beneath the window, see `Config::shadow`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<bool>,
+    /// Blur what is behind the window, see `Config::blur`. Only has an
+    /// effect on windows with an alpha channel.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blur: Option<bool>,
+    /// Round the corners of the window, see `Config::corners`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rounded: Option<bool>,
+    /// GLSL fragment shader the window is drawn with instead of the
+    /// built-in one, OpenGL backend only. Relative to the config file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shader: Option<PathBuf>,
+    /// Colour filters of the window, applied in order before the global
+    /// ones. Not applied along with custom shaders.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_filters: Option<Vec<ColorFilter>>,
+}
+
+impl Effects {
+    /// Overrides the settings that are set in `other`
+    fn merge(&mut self, other: &Effects) {
+        if other.opacity.is_some() {
+            self.opacity = other.opacity;
+        }
+        if other.dim.is_some() {
+            self.dim = other.dim;
+        }
+        if other.shadow.is_some() {
+            self.shadow = other.shadow;
+        }
+        if other.blur.is_some() {
+            self.blur = other.blur;
+        }
+        if other.rounded.is_some() {
+            self.rounded = other.rounded;
+        }
+        if other.shader.is_some() {
+            self.shader = other.shader.clone();
+        }
+        if other.color_filters.is_some() {
+            self.color_filters = other.color_filters.clone();
+        }
+    }
+
+    /// Returns the key of the bad value along with the reason
+    pub fn validate(&self) -> Result<(), (&'static str, &'static str)> {
+        if let Some(opacity) = self.opacity {
+            if !(0.0..=1.0).contains(&opacity) {
+                return Err(("opacity", "must be between 0 and 1"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Computes the settings of a window from the rules matching it
+pub fn apply<C>(rules: &[Rule], win: &mut Window<C>) {
+    let mut effects = Effects::default();
+    for rule in rules.iter().filter(|r| r.condition.matches(win)) {
+        effects.merge(&rule.set);
+    }
+    win.effects = effects;
+}
+
+/// A parsed rule condition, e.g. `type == "dialog" || name =~ "^vim"`.
+///
+/// Supported operators:
+/// - `==`, `!=`: exact string or number comparison
+/// - `~`: shell-like glob (`*`, `?`), anchored to the whole value
+/// - `=~`: regular expression, matches anywhere in the value
+/// - `<`, `<=`, `>`, `>=`: number comparison
+/// - `&&`, `||`, `!` and parentheses to combine them
+///
+/// Boolean fields (`override_redirect`, `alpha`, `fullscreen`) are used on
+/// their own.
+#[derive(Debug, Clone)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn matches<C>(&self, win: &Window<C>) -> bool {
+        self.expr.eval(win)
+    }
+}
+
+impl PartialEq for Condition {
+    fn eq(&self, other: &Condition) -> bool {
+        self.source == other.source
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Condition, String> {
+        let tokens = tokenize(&source)?;
+        let mut parser = Parser { tokens: &tokens, pos: 0 };
+        let expr = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {} in condition", token));
+        }
+        Ok(Condition { source, expr })
+    }
+}
+
+impl From<Condition> for String {
+    fn from(condition: Condition) -> String {
+        condition.source
+    }
+}
+
+impl Serialize for Condition {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Condition {
+    fn deserialize<D>(d: D) -> Result<Condition, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Condition::try_from(String::deserialize(d)?)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Class,
+    Instance,
+    Name,
+    Type,
+    Role,
+    OverrideRedirect,
+    Alpha,
+    Fullscreen,
+    X,
+    Y,
+    Width,
+    Height,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Text,
+    Number,
+    Flag,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        Some(match name {
+            "class" => Field::Class,
+            "instance" => Field::Instance,
+            "name" => Field::Name,
+            "type" => Field::Type,
+            "role" => Field::Role,
+            "override_redirect" => Field::OverrideRedirect,
+            "alpha" => Field::Alpha,
+            "fullscreen" => Field::Fullscreen,
+            "x" => Field::X,
+            "y" => Field::Y,
+            "width" => Field::Width,
+            "height" => Field::Height,
+            _ => return None,
+        })
+    }
+
+    fn kind(self) -> Kind {
+        match self {
+            Field::Class
+            | Field::Instance
+            | Field::Name
+            | Field::Type
+            | Field::Role => Kind::Text,
+            Field::OverrideRedirect | Field::Alpha | Field::Fullscreen => {
+                Kind::Flag
+            }
+            Field::X | Field::Y | Field::Width | Field::Height => Kind::Number,
+        }
+    }
+
+    fn text<C>(self, win: &Window<C>) -> &str {
+        match self {
+            Field::Class => &win.props.class,
+            Field::Instance => &win.props.instance,
+            Field::Name => &win.props.name,
+            Field::Type => &win.props.window_type,
+            Field::Role => &win.props.role,
+            _ => "",
+        }
+    }
+
+    fn number<C>(self, win: &Window<C>) -> f64 {
+        match self {
+            Field::X => win.x as f64,
+            Field::Y => win.y as f64,
+            Field::Width => win.width as f64,
+            Field::Height => win.height as f64,
+            _ => 0.0,
+        }
+    }
+
+    fn flag<C>(self, win: &Window<C>) -> bool {
+        match self {
+            Field::OverrideRedirect => win.override_redirect,
+            Field::Alpha => win.alpha,
+            Field::Fullscreen => win.props.fullscreen,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Test {
+    Equals(String),
+    Pattern(Regex),
+    Compare(CompareOp, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Flag(Field),
+    Test(Field, Test),
+}
+
+impl Expr {
+    fn eval<C>(&self, win: &Window<C>) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(win) && b.eval(win),
+            Expr::Or(a, b) => a.eval(win) || b.eval(win),
+            Expr::Not(a) => !a.eval(win),
+            Expr::Flag(field) => field.flag(win),
+            Expr::Test(field, Test::Equals(value)) => field.text(win) == value,
+            Expr::Test(field, Test::Pattern(re)) => {
+                re.is_match(field.text(win))
+            }
+            Expr::Test(field, Test::Compare(op, value)) => {
+                let n = field.number(win);
+                match op {
+                    CompareOp::Eq => n == *value,
+                    CompareOp::Lt => n < *value,
+                    CompareOp::Le => n <= *value,
+                    CompareOp::Gt => n > *value,
+                    CompareOp::Ge => n >= *value,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(f64),
+    Op(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "`{}`", name),
+            Token::Str(s) => write!(f, "string {:?}", s),
+            Token::Number(n) => write!(f, "number {}", n),
+            Token::Op(op) => write!(f, "`{}`", op),
+        }
+    }
+}
+
+// Longest operators first, so `==` is not read as two `=`
+const OPERATORS: [&str; 13] =
+    ["&&", "||", "==", "!=", "=~", "<=", ">=", "!", "~", "<", ">", "(", ")"];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => value.push(escaped),
+                        None => return Err("unterminated string".into()),
+                    },
+                    Some((_, ch)) if ch == c => break,
+                    Some((_, ch)) => value.push(ch),
+                    None => return Err("unterminated string".into()),
+                }
+            }
+            tokens.push(Token::Str(value));
+        } else if c.is_ascii_digit() || c == '-' {
+            let end = source[i + 1..]
+                .find(|ch: char| !(ch.is_ascii_digit() || ch == '.'))
+                .map_or(source.len(), |end| i + 1 + end);
+            let number = source[i..end].parse().map_err(|_| {
+                format!("invalid number `{}`", &source[i..end])
+            })?;
+            tokens.push(Token::Number(number));
+            while chars.peek().is_some_and(|&(j, _)| j < end) {
+                chars.next();
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let end = source[i..]
+                .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+                .map_or(source.len(), |end| i + end);
+            tokens.push(Token::Ident(source[i..end].to_string()));
+            while chars.peek().is_some_and(|&(j, _)| j < end) {
+                chars.next();
+            }
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| source[i..].starts_with(*op))
+                .ok_or_else(|| format!("unexpected character `{}`", c))?;
+            tokens.push(Token::Op(op));
+            for _ in 0..op.len() {
+                chars.next();
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, op: &'static str) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_unary()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.parse_or()?;
+            if !self.eat(")") {
+                return Err("missing `)` in condition".into());
+            }
+            return Ok(expr);
+        }
+        let name = match self.next() {
+            Some(Token::Ident(name)) => name,
+            Some(token) => {
+                return Err(format!("expected a field, found {}", token))
+            }
+            None => return Err("unexpected end of condition".into()),
+        };
+        let field = Field::from_name(name)
+            .ok_or_else(|| format!("unknown field `{}`", name))?;
+        if field.kind() == Kind::Flag {
+            return Ok(Expr::Flag(field));
+        }
+        let op = match self.next() {
+            Some(Token::Op(op)) => *op,
+            _ => return Err(format!("expected an operator after `{}`", name)),
+        };
+        let value = self.next();
+        let test = match (field.kind(), op, value) {
+            (Kind::Text, "==" | "!=", Some(Token::Str(s))) => {
+                Test::Equals(s.clone())
+            }
+            (Kind::Text, "~", Some(Token::Str(s))) => {
+                Test::Pattern(glob_to_regex(s)?)
+            }
+            (Kind::Text, "=~", Some(Token::Str(s))) => {
+                Test::Pattern(Regex::new(s).map_err(|e| {
+                    // The last line holds the reason, the others draw
+                    // the pattern
+                    let e = e.to_string();
+                    let reason = e.lines().last().unwrap_or_default();
+                    format!("invalid regex {:?}: {}", s, reason)
+                })?)
+            }
+            (Kind::Number, _, Some(Token::Number(n))) => {
+                let op = match op {
+                    "==" | "!=" => CompareOp::Eq,
+                    "<" => CompareOp::Lt,
+                    "<=" => CompareOp::Le,
+                    ">" => CompareOp::Gt,
+                    ">=" => CompareOp::Ge,
+                    _ => {
+                        return Err(format!(
+                            "`{}` cannot be used with `{}`",
+                            op, name
+                        ))
+                    }
+                };
+                Test::Compare(op, *n)
+            }
+            (_, _, Some(token)) => {
+                return Err(format!(
+                    "`{} {}` cannot be used with {}",
+                    name, op, token
+                ))
+            }
+            (_, _, None) => return Err("unexpected end of condition".into()),
+        };
+        let expr = Expr::Test(field, test);
+        Ok(if op == "!=" { Expr::Not(Box::new(expr)) } else { expr })
+    }
+}
+
+/// Converts a glob into an anchored regex
+fn glob_to_regex(glob: &str) -> Result<Regex, String> {
+    let mut re = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            _ => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).map_err(|e| format!("invalid glob: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(source: &str) -> Result<Condition, String> {
+        Condition::try_from(source.to_string())
+    }
+
+    fn matches(source: &str, win: &Window) -> bool {
+        condition(source).unwrap().matches(win)
+    }
+
+    fn firefox() -> Window {
+        let mut win = Window::for_test();
+        win.props.class = "Firefox".into();
+        win.props.name = "Mozilla Firefox".into();
+        win.props.window_type = "normal".into();
+        win
+    }
+
+    #[test]
+    fn compares_text() {
+        let win = firefox();
+        assert!(matches(r#"class == "Firefox""#, &win));
+        assert!(!matches(r#"class != "Firefox""#, &win));
+        assert!(matches(r#"class ~ "Fire*""#, &win));
+        assert!(!matches(r#"class ~ "fire*""#, &win));
+        assert!(matches(r#"name =~ "Fire""#, &win));
+        assert!(matches("type == 'normal'", &win));
+    }
+
+    #[test]
+    fn compares_numbers() {
+        let win = firefox();
+        assert!(matches("width >= 100 && height < 101", &win));
+        assert!(matches("x == 0", &win));
+        assert!(!matches("width > 100", &win));
+        assert!(matches("x > -1", &win));
+    }
+
+    #[test]
+    fn combines_expressions() {
+        let win = firefox();
+        assert!(matches("!override_redirect && !alpha", &win));
+        assert!(matches(r#"alpha || class == "Firefox""#, &win));
+        // `&&` binds tighter than `||`
+        assert!(matches(
+            r#"class == "x" && alpha || !override_redirect"#,
+            &win
+        ));
+        assert!(!matches(
+            r#"class == "x" && (alpha || !override_redirect)"#,
+            &win
+        ));
+    }
+
+    #[test]
+    fn glob_is_anchored() {
+        let win = firefox();
+        assert!(!matches(r#"class ~ "Fire""#, &win));
+        assert!(matches(r#"class ~ "Fire?ox""#, &win));
+        assert!(!matches(r#"class ~ "F.*""#, &win));
+    }
+
+    #[test]
+    fn rejects_invalid_conditions() {
+        assert_eq!(
+            condition("opacity == 1").unwrap_err(),
+            "unknown field `opacity`"
+        );
+        assert_eq!(
+            condition("(alpha").unwrap_err(),
+            "missing `)` in condition"
+        );
+        assert_eq!(
+            condition("class == 'a").unwrap_err(),
+            "unterminated string"
+        );
+        assert_eq!(
+            condition("width ~ 3").unwrap_err(),
+            "`~` cannot be used with `width`"
+        );
+        assert_eq!(
+            condition("class < 'a'").unwrap_err(),
+            "`class <` cannot be used with string \"a\""
+        );
+        assert_eq!(
+            condition("alpha alpha").unwrap_err(),
+            "unexpected `alpha` in condition"
+        );
+        assert!(condition("name =~ '('").is_err());
+        assert_eq!(condition("").unwrap_err(), "unexpected end of condition");
+    }
+}
@@ -0,0 +1,146 @@
+use crate::config::Config;
+use crate::output;
+use crate::region::Region;
+use std::time::{Duration, Instant};
+
+/// Rate frames are drawn at when no output reports one
+const DEFAULT_REFRESH: f64 = 60.0;
+
+/// Decides when frames get drawn.
+///
+/// Events only ask for a frame, gathering what they changed, and the
+/// frame is drawn once at least `interval` has passed since the previous
+/// one. A pending frame is never dropped, the event loop wakes up by its
+/// deadline to draw it.
+pub struct Scheduler {
+    /// Shortest time between two frames
+    interval: Duration,
+    last_frame: Instant,
+    /// Part of the screen the pending frame needs to redraw, `None` when
+    /// no frame is pending
+    damage: Option<Region>,
+}
+
+impl Scheduler {
+    pub fn new(interval: Duration) -> Scheduler {
+        Scheduler {
+            interval,
+            // The first frame is due right away
+            last_frame: Instant::now()
+                .checked_sub(interval)
+                .unwrap_or_else(Instant::now),
+            damage: None,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Asks for a frame redrawing at least `area`
+    pub fn request(&mut self, area: Region) {
+        self.damage = Some(match self.damage.take() {
+            Some(damage) => damage.union(area),
+            None => area,
+        });
+    }
+
+    /// Asks for a frame redrawing everything
+    pub fn request_full(&mut self) {
+        self.request(Region::Full);
+    }
+
+    /// Time left until the pending frame is due, `None` without one
+    pub fn timeout(&self) -> Option<Duration> {
+        self.damage.as_ref()?;
+        let deadline = self.last_frame + self.interval;
+        Some(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Takes the damage of the pending frame if it is due, the frame
+    /// being then counted as drawn
+    pub fn take_due(&mut self) -> Option<Region> {
+        if self.timeout()? > Duration::ZERO {
+            return None;
+        }
+        self.last_frame = Instant::now();
+        self.damage.take()
+    }
+}
+
+/// Shortest time between two frames: `frame_time` when set, one refresh
+/// of the fastest output otherwise
+pub fn frame_interval(
+    conn: &xcb::Connection,
+    root: xcb::Window,
+    config: &Config,
+) -> Duration {
+    if let Some(frame_time) = config.frame_time {
+        return Duration::from_millis(frame_time);
+    }
+    let refresh = output::outputs(conn, root)
+        .iter()
+        .map(|output| output.refresh)
+        .fold(0.0, f64::max);
+    let refresh = if refresh > 0.0 {
+        refresh
+    } else {
+        warn!(
+            "Unable to get the refresh rate, assuming {} Hz",
+            DEFAULT_REFRESH
+        );
+        DEFAULT_REFRESH
+    };
+    info!("Drawing at most {:.2} frames per second", refresh);
+    Duration::from_secs_f64(1.0 / refresh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn draws_the_first_frame_right_away() {
+        let mut scheduler = Scheduler::new(HOUR);
+        assert_eq!(scheduler.timeout(), None);
+        assert_eq!(scheduler.take_due(), None);
+        scheduler.request_full();
+        assert_eq!(scheduler.timeout(), Some(Duration::ZERO));
+        assert_eq!(scheduler.take_due(), Some(Region::Full));
+        assert_eq!(scheduler.take_due(), None);
+    }
+
+    #[test]
+    fn waits_an_interval_between_frames() {
+        let mut scheduler = Scheduler::new(HOUR);
+        scheduler.request_full();
+        scheduler.take_due();
+        scheduler.request(Region::Rects(vec![[0, 0, 10, 10]]));
+        assert!(scheduler.timeout().unwrap() > HOUR / 2);
+        assert_eq!(scheduler.take_due(), None);
+        // Shorter intervals apply to the pending frame
+        scheduler.set_interval(Duration::ZERO);
+        assert_eq!(scheduler.timeout(), Some(Duration::ZERO));
+        assert!(scheduler.take_due().is_some());
+    }
+
+    #[test]
+    fn gathers_damage_until_the_frame() {
+        let mut scheduler = Scheduler::new(Duration::ZERO);
+        scheduler.request(Region::Rects(vec![[0, 0, 10, 10]]));
+        scheduler.request(Region::Rects(vec![[50, 0, 10, 10]]));
+        assert_eq!(
+            scheduler.take_due(),
+            Some(Region::Rects(vec![[0, 0, 10, 10], [50, 0, 10, 10]]))
+        );
+        scheduler.request(Region::Rects(vec![[0, 0, 10, 10]]));
+        scheduler.request_full();
+        assert_eq!(scheduler.take_due(), Some(Region::Full));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::corners;
+use crate::window::Window;
+
+/// Alpha mask of a window shadow: the bounding shape of the window, with
+/// its rounded corners, blurred by the shadow radius.
+///
+/// The mask is `radius` pixels larger than the window on every side, and
+/// is computed on the CPU so every backend can draw the same shadow.
+#[derive(Default)]
+pub struct Mask {
+    pub width: usize,
+    pub height: usize,
+    /// One byte of alpha per pixel, row-major
+    pub data: Vec<u8>,
+}
+
+impl Mask {
+    pub fn build<C: Default>(
+        win: &Window<C>,
+        radius: u16,
+        corner_radius: u16,
+    ) -> Mask {
+        let radius = radius as usize;
+        let width = win.outer_width() as usize + radius * 2;
+        let height = win.outer_height() as usize + radius * 2;
+        let mut data = vec![0u8; width * height];
+
+        for rect in &win.shape {
+            // Shape rectangles are relative to the outer corner, and can
+            // reach past the mask when the shape is stale or larger than
+            // the window
+            let (x, y) = (rect.x() as isize, rect.y() as isize);
+            let margin = radius as isize;
+            let x0 = (x + margin).max(0) as usize;
+            let y0 = (y + margin).max(0) as usize;
+            let x1 = (x + rect.width() as isize + margin)
+                .clamp(0, width as isize) as usize;
+            let y1 = (y + rect.height() as isize + margin)
+                .clamp(0, height as isize) as usize;
+            if x0 >= x1 || y0 >= y1 {
+                continue;
+            }
+            for y in y0..y1 {
+                data[y * width + x0..y * width + x1].fill(255);
+            }
+        }
+        if corner_radius > 0 {
+            let (win_width, win_height) =
+                (win.outer_width() as f32, win.outer_height() as f32);
+            for (i, value) in data.iter_mut().enumerate() {
+                let x = (i % width) as f32 - radius as f32 + 0.5;
+                let y = (i / width) as f32 - radius as f32 + 0.5;
+                let dist = corners::distance(
+                    x,
+                    y,
+                    win_width,
+                    win_height,
+                    corner_radius as f32,
+                );
+                *value = (*value as f32 * corners::coverage(dist)) as u8;
+            }
+        }
+
+        // Three box blurs are close enough to a gaussian one
+        let box_radius = (radius / 3).max(1);
+        if radius > 0 {
+            for _ in 0..3 {
+                blur(&mut data, width, height, box_radius, 1, width);
+                blur(&mut data, height, width, box_radius, width, 1);
+            }
+        }
+        Mask { width, height, data }
+    }
+}
+
+/// Box blur along one axis, out of bounds pixels count as 0.
+///
+/// `len` pixels of each of the `lines` lines are `step` apart, and lines
+/// start `stride` apart.
+pub fn blur(
+    data: &mut [u8],
+    len: usize,
+    lines: usize,
+    radius: usize,
+    step: usize,
+    stride: usize,
+) {
+    let size = (radius * 2 + 1) as u32;
+    let mut line = vec![0u8; len];
+    for l in 0..lines {
+        let start = l * stride;
+        for (i, value) in line.iter_mut().enumerate() {
+            *value = data[start + i * step];
+        }
+        // Running sum of the window around `i`, out of bounds is 0
+        let mut sum: u32 =
+            line[..radius.min(len)].iter().map(|v| *v as u32).sum();
+        for i in 0..len {
+            if i + radius < len {
+                sum += line[i + radius] as u32;
+            }
+            data[start + i * step] = (sum / size) as u8;
+            if i >= radius {
+                sum -= line[i - radius] as u32;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Columns of `row` holding any alpha, from the first to past the last
+    fn extent(mask: &Mask, row: usize) -> (usize, usize) {
+        let line = &mask.data[row * mask.width..(row + 1) * mask.width];
+        let first = line.iter().position(|&a| a > 0).unwrap_or(line.len());
+        let last = line.iter().rposition(|&a| a > 0).map_or(0, |i| i + 1);
+        (first, last)
+    }
+
+    #[test]
+    fn skips_shape_rects_past_the_mask() {
+        let mut win = Window::for_test();
+        win.shape = vec![
+            xcb::Rectangle::new(0, 0, 100, 100),
+            // Left over from before the window shrank
+            xcb::Rectangle::new(150, 150, 50, 50),
+        ];
+        let mask = Mask::build(&win, 0, 0);
+        assert_eq!((mask.width, mask.height), (100, 100));
+        assert!(mask.data.iter().all(|&a| a == 255));
+    }
+
+    #[test]
+    fn clips_shape_rects_before_the_mask() {
+        let mut win = Window::for_test();
+        win.shape = vec![
+            xcb::Rectangle::new(-30, 10, 50, 20),
+            xcb::Rectangle::new(-80, 40, 40, 20),
+        ];
+        let mask = Mask::build(&win, 0, 0);
+        // Keeps its right edge instead of being shifted right
+        assert_eq!(extent(&mask, 20), (0, 20));
+        assert_eq!(extent(&mask, 50), (100, 0));
+        assert_eq!(extent(&mask, 0), (100, 0));
+    }
+
+    #[test]
+    fn keeps_shape_rects_in_the_margin() {
+        let mut win = Window::for_test();
+        win.shape = vec![xcb::Rectangle::new(-5, 0, 10, 100)];
+        let mask = Mask::build(&win, 6, 0);
+        assert_eq!(extent(&mask, 50).0, 0);
+        assert!(extent(&mask, 50).1 < 20);
+    }
+}
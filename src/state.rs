@@ -1,7 +1,9 @@
 use std::process::exit;
 
+use crate::atoms::Atoms;
 use crate::init;
 use crate::Window;
+use xcb::{randr, xfixes};
 
 pub struct State {
     /// The X11 connection
@@ -22,10 +24,22 @@ pub struct State {
     pub overlay: xcb::Window,
     /// Window id of the compositor
     pub win_id: xcb::Window,
+    /// Interned atoms
+    pub atoms: Atoms,
+    /// Response type of SHAPE notify events
+    pub shape_event: u8,
+    /// Response type of DAMAGE notify events
+    pub damage_event: u8,
+    /// Response type of the first RandR event, screen change notify
+    pub randr_event: u8,
+    /// Scratch region damage gets moved through
+    pub damage_region: xfixes::Region,
 }
 
 impl State {
-    pub fn init() -> Result<State, xcb::ConnError> {
+    /// Connects to the X server and sets up compositing, replacing any
+    /// running compositor if `replace` is set.
+    pub fn init(replace: bool) -> Result<State, xcb::ConnError> {
         let (conn, xlib_screens) =
             xcb::Connection::connect_with_xlib_display()?;
         conn.set_event_queue_owner(xcb::EventQueueOwner::Xcb);
@@ -38,6 +52,14 @@ impl State {
             eprintln!("Error: extension `{}` not found.", err);
             exit(1);
         });
+        init::extensions::query_versions(&conn).unwrap_or_else(|err| {
+            eprintln!("Error: unable to query extension versions: {}", err);
+            exit(1);
+        });
+
+        // Has to happen before redirecting, only one client at a time can
+        // redirect windows
+        let win_id = init::window::create_window(&conn, replace);
 
         let overlay = init::extensions::redirect_subwindows(&conn)
             .unwrap_or_else(|err| {
@@ -45,16 +67,54 @@ impl State {
                 exit(1);
             });
 
-        let win_id = init::window::create_window(&conn);
+        let atoms = Atoms::intern(&conn).unwrap();
+        // Presence was checked by `verify`
+        let shape_event =
+            conn.get_extension_data(xcb::shape::id()).unwrap().first_event()
+                + xcb::shape::NOTIFY;
+        let damage_event =
+            conn.get_extension_data(xcb::damage::id()).unwrap().first_event()
+                + xcb::damage::NOTIFY;
+        let randr_event =
+            conn.get_extension_data(randr::id()).unwrap().first_event();
+        // Outputs have their own LUTs and refresh rates
+        randr::select_input(
+            &conn,
+            root.id,
+            (randr::NOTIFY_MASK_SCREEN_CHANGE | randr::NOTIFY_MASK_CRTC_CHANGE)
+                as u16,
+        );
+
+        let damage_region = conn.generate_id();
+        xfixes::create_region(&conn, damage_region, &[]);
 
-        Ok(State { conn, xlib_screens, xcb_screens, root, overlay, win_id })
+        Ok(State {
+            conn,
+            xlib_screens,
+            xcb_screens,
+            root,
+            overlay,
+            win_id,
+            atoms,
+            shape_event,
+            damage_event,
+            randr_event,
+            damage_region,
+        })
     }
+    /// Stops compositing and gives up the `_NET_WM_CM_Sn` selections
+    pub fn release(&self) {
+        init::extensions::unredirect_subwindows(&self.conn);
+        xcb::destroy_window(&self.conn, self.win_id);
+        self.conn.flush();
+    }
+
     pub fn update_root_pixmap(&mut self) {
         self.root.pixmap = match get_root_pixmap(&self.conn, &self.root) {
             Ok(pixmap) => pixmap,
             // TODO: create new 1x1 pixmap
             Err(message) => {
-                eprintln!("{}", message);
+                warn!("{}", message);
                 xcb::NONE
             }
         };
@@ -1,7 +1,15 @@
-use crate::opengl::BackendContext;
+use crate::anim::{Animation, Transform};
+use crate::atoms::Atoms;
+use crate::filter::{self, ColorFilter, ColorMatrix};
+use crate::region;
+use crate::rules::Effects;
+use std::time::{Duration, Instant};
 use xcb::{composite, damage, render, shape};
 
-pub struct Window {
+/// A tracked top-level window.
+///
+/// `C` is the per-window data of the backend used for drawing.
+pub struct Window<C = ()> {
     pub id: xcb::Window,
     pub x: i16,
     pub y: i16,
@@ -11,23 +19,89 @@ pub struct Window {
     pub mapped: bool,
     pub override_redirect: bool,
     pub alpha: bool,
+    pub visual: xcb::Visualid,
     pub pixmap: xcb::Pixmap,
-    pub context: BackendContext,
+    pub context: C,
     pub damage: damage::Damage,
+    /// Bounding shape, relative to the outer corner of the window
+    pub shape: Vec<xcb::Rectangle>,
+    /// Window holding the client properties, either the window itself or
+    /// a descendant when it is a window manager frame
+    pub client: xcb::Window,
+    pub props: Properties,
+    /// Opacity set with `_NET_WM_WINDOW_OPACITY`, between 0 and 1
+    pub opacity: f32,
+    /// Region the client asked to be blurred with
+    /// `_KDE_NET_WM_BLUR_BEHIND_REGION`, in client coordinates. Empty
+    /// when the whole window is requested.
+    pub blur_region: Option<Vec<xcb::Rectangle>>,
+    /// Position of the client inside the window, from its inner corner
+    pub client_offset: (i16, i16),
+    /// Colour filters set at runtime with `_RCOMP_COLOR_FILTERS`, they
+    /// take precedence over the rules
+    pub color_filters: Option<Vec<ColorFilter>>,
+    /// Opacity animation in progress
+    pub fade: Option<Fade>,
+    /// Open or close animation in progress
+    pub anim: Option<Animation>,
+    /// Set on destroyed windows kept until their animations are over
+    pub destroyed: bool,
+    /// Set when the client is `_NET_ACTIVE_WINDOW`
+    pub focused: bool,
+    /// Settings from the rules matching this window
+    pub effects: Effects,
+    /// Last resize since the masks were built, they are rebuilt once
+    /// it settles
+    pub resized: Option<Instant>,
 }
 
-impl Window {
+/// Time-based opacity animation
+#[derive(Debug, Clone, Copy)]
+pub struct Fade {
+    from: f32,
+    to: f32,
+    start: Instant,
+    duration: Duration,
+    /// Opacity as of the last step
+    pub current: f32,
+}
+
+/// Client window properties, used for matching rules
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Properties {
+    /// WM_CLASS instance name
+    pub instance: String,
+    /// WM_CLASS class name
+    pub class: String,
+    /// _NET_WM_NAME, or WM_NAME if not set
+    pub name: String,
+    /// _NET_WM_WINDOW_TYPE without the `_NET_WM_WINDOW_TYPE_` prefix,
+    /// lowercased ("normal", "dialog", "dock", ...)
+    pub window_type: String,
+    /// WM_WINDOW_ROLE
+    pub role: String,
+    /// `_NET_WM_STATE` contains `_NET_WM_STATE_FULLSCREEN`
+    pub fullscreen: bool,
+}
+
+impl<C: Default> Window<C> {
     /// Returns a vector of mapped `Windows` recieved from `XQueryTree`
-    pub fn fetch_windows(conn: &xcb::Connection) -> Vec<Window> {
+    pub fn fetch_windows(
+        conn: &xcb::Connection,
+        atoms: &Atoms,
+    ) -> Vec<Window<C>> {
         let setup = conn.get_setup();
         let screen = setup.roots().last().unwrap();
         let tree = xcb::query_tree(conn, screen.root()).get_reply().unwrap();
-        let mut windows: Vec<Window> =
+        let mut windows: Vec<Window<C>> =
             Vec::with_capacity(tree.children_len() as usize);
         for win in tree.children().iter().rev() {
             match Window::new(conn, *win) {
-                Ok(w) => windows.push(w),
-                Err(_) => println!("Unable to get info for win: {}", win),
+                Ok(mut w) => {
+                    w.update_client(conn, atoms);
+                    windows.push(w);
+                }
+                Err(_) => warn!("Unable to get info for win: {}", win),
             };
         }
         windows
@@ -37,7 +111,7 @@ impl Window {
     pub fn new(
         conn: &xcb::Connection,
         win: xcb::Window,
-    ) -> Result<Window, xcb::GenericError> {
+    ) -> Result<Window<C>, xcb::GenericError> {
         let geometry = xcb::get_geometry(conn, win).get_reply()?;
         let attrs = xcb::get_window_attributes(conn, win).get_reply()?;
 
@@ -51,12 +125,381 @@ impl Window {
             mapped: attrs.map_state() == xcb::MAP_STATE_VIEWABLE as u8,
             override_redirect: attrs.override_redirect(),
             alpha: has_alpha(conn, attrs.colormap()),
+            visual: attrs.visual(),
             pixmap: conn.generate_id(),
             context: Default::default(),
             damage: 0,
+            shape: Vec::new(),
+            client: win,
+            props: Default::default(),
+            opacity: 1.0,
+            blur_region: None,
+            client_offset: (0, 0),
+            color_filters: None,
+            fade: None,
+            anim: None,
+            destroyed: false,
+            focused: false,
+            effects: Default::default(),
+            resized: None,
         })
     }
 
+    /// Finds the client window and fetches its properties.
+    ///
+    /// Window managers reparent clients into frames after the frame is
+    /// created, so this has to be repeated once the window gets mapped.
+    pub fn update_client(&mut self, conn: &xcb::Connection, atoms: &Atoms) {
+        self.client = find_client(conn, atoms, self.id, 3).unwrap_or(self.id);
+        // Needed for rules to follow property changes, added to the events
+        // already selected on the window
+        for win in [self.id, self.client] {
+            let mask = xcb::get_window_attributes(conn, win)
+                .get_reply()
+                .map_or(0, |attrs| attrs.your_event_mask());
+            xcb::change_window_attributes(
+                conn,
+                win,
+                &[(
+                    xcb::CW_EVENT_MASK,
+                    mask | xcb::EVENT_MASK_PROPERTY_CHANGE,
+                )],
+            );
+        }
+        self.props = Properties {
+            instance: String::new(),
+            class: String::new(),
+            name: fetch_name(conn, atoms, self.client),
+            window_type: fetch_window_type(conn, atoms, self.client),
+            role: fetch_string(conn, self.client, atoms.wm_window_role),
+            fullscreen: fetch_fullscreen(conn, atoms, self.client),
+        };
+        let (instance, class) = fetch_class(conn, self.client);
+        self.props.instance = instance;
+        self.props.class = class;
+        self.opacity = fetch_opacity(conn, atoms, self.id, self.client);
+        self.update_client_offset(conn);
+        self.blur_region = self.fetch_blur_region(conn, atoms);
+        self.color_filters =
+            filter::fetch(conn, self.client, atoms.rcomp_color_filters);
+    }
+
+    /// Refetches a changed property of the client window.
+    /// Returns true if the property is one tracked by `Window`.
+    pub fn update_property(
+        &mut self,
+        conn: &xcb::Connection,
+        atoms: &Atoms,
+        atom: xcb::Atom,
+    ) -> bool {
+        if atom == xcb::ATOM_WM_CLASS {
+            let (instance, class) = fetch_class(conn, self.client);
+            self.props.instance = instance;
+            self.props.class = class;
+        } else if atom == xcb::ATOM_WM_NAME || atom == atoms.net_wm_name {
+            self.props.name = fetch_name(conn, atoms, self.client);
+        } else if atom == atoms.net_wm_window_type {
+            self.props.window_type =
+                fetch_window_type(conn, atoms, self.client);
+        } else if atom == atoms.wm_window_role {
+            self.props.role =
+                fetch_string(conn, self.client, atoms.wm_window_role);
+        } else if atom == atoms.net_wm_state {
+            self.props.fullscreen = fetch_fullscreen(conn, atoms, self.client);
+        } else if atom == atoms.kde_net_wm_blur_behind_region {
+            self.blur_region = self.fetch_blur_region(conn, atoms);
+        } else if atom == atoms.net_wm_window_opacity {
+            self.opacity = fetch_opacity(conn, atoms, self.id, self.client);
+        } else if atom == atoms.rcomp_color_filters {
+            self.color_filters =
+                filter::fetch(conn, self.client, atoms.rcomp_color_filters);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Opacity the window settles at, rules take precedence over the
+    /// window property
+    pub fn target_opacity(&self) -> f32 {
+        self.effects.opacity.unwrap_or(self.opacity)
+    }
+
+    /// Opacity the window is drawn with right now
+    pub fn draw_opacity(&self) -> f32 {
+        self.fade.map_or(self.target_opacity(), |fade| fade.current)
+    }
+
+    /// Whether the window has to be drawn: mapped, or animating out
+    pub fn visible(&self) -> bool {
+        self.mapped || self.animating()
+    }
+
+    /// Whether a fade or an open/close animation is in progress
+    pub fn animating(&self) -> bool {
+        self.fade.is_some() || self.anim.is_some()
+    }
+
+    /// Advances the open/close animation to `now`, returns false once it
+    /// is over
+    pub fn step_anim(&mut self, now: Instant) -> bool {
+        let running = self.anim.as_mut().is_some_and(|anim| anim.step(now));
+        if !running {
+            self.anim = None;
+        }
+        running
+    }
+
+    /// Transform the window is drawn with right now
+    pub fn transform(&self) -> Transform {
+        self.anim.map_or(Transform::IDENTITY, |anim| {
+            anim.transform(self.outer_height() as f32)
+        })
+    }
+
+    /// Where a rectangle given relative to the outer corner of the window
+    /// ends up on screen, as [x, y, width, height]
+    pub fn draw_rect(
+        &self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) -> [f32; 4] {
+        let Transform { scale, offset } = self.transform();
+        // Scaled around the center of the window
+        let cx = self.outer_width() as f32 / 2.0;
+        let cy = self.outer_height() as f32 / 2.0;
+        [
+            self.x as f32 + cx + (x - cx) * scale + offset[0],
+            self.y as f32 + cy + (y - cy) * scale + offset[1],
+            width * scale,
+            height * scale,
+        ]
+    }
+
+    /// Starts fading from `from` to `to`, a zero duration ends any fade
+    pub fn start_fade(&mut self, from: f32, to: f32, duration: Duration) {
+        self.fade = if duration.is_zero() || from == to {
+            None
+        } else {
+            Some(Fade {
+                from,
+                to,
+                start: Instant::now(),
+                duration,
+                current: from,
+            })
+        };
+    }
+
+    /// Advances the fade to `now`, returns false once it is over
+    pub fn step_fade(&mut self, now: Instant) -> bool {
+        let fade = match &mut self.fade {
+            Some(fade) => fade,
+            None => return false,
+        };
+        let progress = now.duration_since(fade.start).as_secs_f32()
+            / fade.duration.as_secs_f32();
+        if progress >= 1.0 {
+            self.fade = None;
+            return false;
+        }
+        fade.current = fade.from + (fade.to - fade.from) * progress;
+        true
+    }
+
+    /// Whether the window is drawn darkened by `Config::inactive_dim`.
+    /// Docks and desktops are never focused, and menus and tooltips
+    /// belong to the focused window, so they are left alone unless a rule
+    /// says otherwise.
+    pub fn dimmed(&self) -> bool {
+        let default = !self.override_redirect
+            && !matches!(
+                self.props.window_type.as_str(),
+                "dock"
+                    | "desktop"
+                    | "menu"
+                    | "dropdown_menu"
+                    | "popup_menu"
+                    | "tooltip"
+                    | "combo"
+                    | "dnd"
+            );
+        !self.focused && self.effects.dim.unwrap_or(default)
+    }
+
+    /// Whether a shadow is drawn beneath the window, `enabled` being the
+    /// global setting. Docks, desktops and fullscreen windows get none
+    /// unless a rule says otherwise.
+    pub fn has_shadow(&self, enabled: bool) -> bool {
+        let default = enabled
+            && !self.props.fullscreen
+            && !matches!(self.props.window_type.as_str(), "dock" | "desktop");
+        self.effects.shadow.unwrap_or(default)
+    }
+
+    /// Whether the window gets rounded corners. Docks, desktops,
+    /// fullscreen and shaped windows keep theirs square unless a rule says
+    /// otherwise.
+    pub fn rounded(&self) -> bool {
+        let default = !self.props.fullscreen
+            && !self.shaped()
+            && !matches!(self.props.window_type.as_str(), "dock" | "desktop");
+        self.effects.rounded.unwrap_or(default)
+    }
+
+    /// Radius of the corners of the window, `radius` being the global
+    /// setting. Never more than half the window size.
+    pub fn corner_radius(&self, radius: u16) -> u16 {
+        if !self.rounded() {
+            return 0;
+        }
+        radius.min(self.outer_width() / 2).min(self.outer_height() / 2)
+    }
+
+    /// Whether the bounding shape is anything but the window rectangle
+    pub fn shaped(&self) -> bool {
+        match self.shape.as_slice() {
+            [rect] => {
+                rect.x() != 0
+                    || rect.y() != 0
+                    || rect.width() != self.outer_width()
+                    || rect.height() != self.outer_height()
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether what is behind the window gets blurred, either requested
+    /// by the client or set by rules
+    pub fn has_blur(&self) -> bool {
+        self.alpha && self.effects.blur.unwrap_or(self.blur_region.is_some())
+    }
+
+    /// Colour transform of the filters the window is drawn with
+    pub fn color_matrix(&self) -> ColorMatrix {
+        let filters = self
+            .color_filters
+            .as_deref()
+            .or(self.effects.color_filters.as_deref())
+            .unwrap_or(&[]);
+        ColorMatrix::new(filters)
+    }
+
+    /// Rectangles behind which the blur is drawn, relative to the outer
+    /// corner of the window and clipped to its shape
+    pub fn blur_rects(&self) -> Vec<xcb::Rectangle> {
+        let region = match &self.blur_region {
+            Some(region) if !region.is_empty() => region,
+            _ => return self.shape.clone(),
+        };
+        let (dx, dy) = self.client_offset;
+        let border = self.border_width as i32;
+        let mut rects = Vec::new();
+        for r in region {
+            let [x, y, width, height] = rect_array(r);
+            let rect = [
+                x + dx as i32 + border,
+                y + dy as i32 + border,
+                width,
+                height,
+            ];
+            for shape in &self.shape {
+                if let Some([x, y, width, height]) =
+                    region::intersection(rect, rect_array(shape))
+                {
+                    rects.push(xcb::Rectangle::new(
+                        x as i16,
+                        y as i16,
+                        width as u16,
+                        height as u16,
+                    ));
+                }
+            }
+        }
+        rects
+    }
+
+    /// Finds where the client sits inside the window, which changes when
+    /// it gets reparented or the frame gets resized
+    pub fn update_client_offset(&mut self, conn: &xcb::Connection) {
+        self.client_offset = if self.client == self.id {
+            (0, 0)
+        } else {
+            xcb::translate_coordinates(conn, self.client, self.id, 0, 0)
+                .get_reply()
+                .map_or((0, 0), |r| (r.dst_x(), r.dst_y()))
+        };
+    }
+
+    /// Reads `_KDE_NET_WM_BLUR_BEHIND_REGION` from the client, made of
+    /// (x, y, width, height) cardinals in client coordinates
+    fn fetch_blur_region(
+        &self,
+        conn: &xcb::Connection,
+        atoms: &Atoms,
+    ) -> Option<Vec<xcb::Rectangle>> {
+        let reply = fetch_property(
+            conn,
+            self.client,
+            atoms.kde_net_wm_blur_behind_region,
+            xcb::ATOM_CARDINAL,
+        )?;
+        Some(
+            reply
+                .value::<u32>()
+                .chunks_exact(4)
+                .map(|r| {
+                    xcb::Rectangle::new(
+                        r[0] as i16,
+                        r[1] as i16,
+                        r[2] as u16,
+                        r[3] as u16,
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Width including the borders
+    pub fn outer_width(&self) -> u16 {
+        self.width + self.border_width * 2
+    }
+
+    /// Height including the borders
+    pub fn outer_height(&self) -> u16 {
+        self.height + self.border_width * 2
+    }
+
+    /// Fetches the bounding shape of the window.
+    /// Unshaped windows get a single rectangle covering them.
+    pub fn update_shape(&mut self, conn: &xcb::Connection) {
+        let rects =
+            shape::get_rectangles(conn, self.id, shape::SK_BOUNDING as u8)
+                .get_reply();
+        let border = self.border_width as i16;
+        self.shape = match rects {
+            Ok(reply) => reply
+                .rectangles()
+                .map(|r| {
+                    xcb::Rectangle::new(
+                        r.x() + border,
+                        r.y() + border,
+                        r.width(),
+                        r.height(),
+                    )
+                })
+                .collect(),
+            Err(_) => vec![xcb::Rectangle::new(
+                0,
+                0,
+                self.outer_width(),
+                self.outer_height(),
+            )],
+        };
+    }
+
     /// Update the geometry properties of an existing `Window`
     pub fn update_geometry(&mut self, conn: &xcb::Connection) {
         let geometry = xcb::get_geometry(conn, self.id).get_reply().unwrap();
@@ -102,10 +545,168 @@ impl Window {
         // Request shape events
         // TODO: this only needs to be done once per window
         shape::select_input(conn, self.id, true).request_check()?;
+        // Unshaped windows follow their size
+        self.update_shape(conn);
         Ok(())
     }
 }
 
+/// Marks the window holding `active` as focused, and the others as not.
+/// Returns true if any of them changed.
+pub fn set_focus<C>(windows: &mut [Window<C>], active: xcb::Window) -> bool {
+    let mut changed = false;
+    for win in windows.iter_mut() {
+        let focused =
+            active != xcb::NONE && (win.id == active || win.client == active);
+        changed |= win.focused != focused;
+        win.focused = focused;
+    }
+    changed
+}
+
+/// Reads `_NET_ACTIVE_WINDOW` from the root window
+pub fn fetch_active(
+    conn: &xcb::Connection,
+    atoms: &Atoms,
+    root: xcb::Window,
+) -> xcb::Window {
+    fetch_property(conn, root, atoms.net_active_window, xcb::ATOM_WINDOW)
+        .and_then(|reply| reply.value::<xcb::Window>().first().copied())
+        .unwrap_or(xcb::NONE)
+}
+
+/// Looks for the window with WM_STATE set, up to `depth` levels down
+fn find_client(
+    conn: &xcb::Connection,
+    atoms: &Atoms,
+    win: xcb::Window,
+    depth: u8,
+) -> Option<xcb::Window> {
+    let has_state = xcb::get_property(
+        conn,
+        false,
+        win,
+        atoms.wm_state,
+        xcb::ATOM_ANY,
+        0,
+        0,
+    )
+    .get_reply()
+    .is_ok_and(|reply| reply.type_() != xcb::NONE);
+    if has_state {
+        return Some(win);
+    }
+    if depth == 0 {
+        return None;
+    }
+    let tree = xcb::query_tree(conn, win).get_reply().ok()?;
+    tree.children()
+        .iter()
+        .find_map(|child| find_client(conn, atoms, *child, depth - 1))
+}
+
+fn fetch_property(
+    conn: &xcb::Connection,
+    win: xcb::Window,
+    atom: xcb::Atom,
+    type_: xcb::Atom,
+) -> Option<xcb::GetPropertyReply> {
+    xcb::get_property(conn, false, win, atom, type_, 0, 1024)
+        .get_reply()
+        .ok()
+        .filter(|reply| reply.type_() != xcb::NONE)
+}
+
+fn fetch_string(
+    conn: &xcb::Connection,
+    win: xcb::Window,
+    atom: xcb::Atom,
+) -> String {
+    fetch_property(conn, win, atom, xcb::ATOM_ANY)
+        .map(|reply| String::from_utf8_lossy(reply.value::<u8>()).into_owned())
+        .unwrap_or_default()
+}
+
+fn fetch_name(
+    conn: &xcb::Connection,
+    atoms: &Atoms,
+    win: xcb::Window,
+) -> String {
+    let name = fetch_string(conn, win, atoms.net_wm_name);
+    if name.is_empty() {
+        fetch_string(conn, win, xcb::ATOM_WM_NAME)
+    } else {
+        name
+    }
+}
+
+/// Returns the (instance, class) pair of WM_CLASS
+fn fetch_class(conn: &xcb::Connection, win: xcb::Window) -> (String, String) {
+    let value = fetch_string(conn, win, xcb::ATOM_WM_CLASS);
+    let mut parts = value.split('\0');
+    (
+        parts.next().unwrap_or_default().to_string(),
+        parts.next().unwrap_or_default().to_string(),
+    )
+}
+
+/// Reads `_NET_WM_WINDOW_OPACITY` from the frame, where some window
+/// managers copy it, or else from the client
+fn fetch_opacity(
+    conn: &xcb::Connection,
+    atoms: &Atoms,
+    frame: xcb::Window,
+    client: xcb::Window,
+) -> f32 {
+    [frame, client]
+        .iter()
+        .find_map(|win| {
+            fetch_property(
+                conn,
+                *win,
+                atoms.net_wm_window_opacity,
+                xcb::ATOM_CARDINAL,
+            )
+            .and_then(|reply| reply.value::<u32>().first().copied())
+        })
+        .map_or(1.0, |value| value as f32 / u32::MAX as f32)
+}
+
+fn fetch_fullscreen(
+    conn: &xcb::Connection,
+    atoms: &Atoms,
+    win: xcb::Window,
+) -> bool {
+    fetch_property(conn, win, atoms.net_wm_state, xcb::ATOM_ATOM).is_some_and(
+        |reply| {
+            reply.value::<xcb::Atom>().contains(&atoms.net_wm_state_fullscreen)
+        },
+    )
+}
+
+fn fetch_window_type(
+    conn: &xcb::Connection,
+    atoms: &Atoms,
+    win: xcb::Window,
+) -> String {
+    let atom =
+        fetch_property(conn, win, atoms.net_wm_window_type, xcb::ATOM_ATOM)
+            .and_then(|reply| reply.value::<xcb::Atom>().first().copied());
+    let name = atom.and_then(|atom| {
+        xcb::get_atom_name(conn, atom)
+            .get_reply()
+            .ok()
+            .map(|reply| reply.name().to_string())
+    });
+    match name {
+        Some(name) => {
+            name.trim_start_matches("_NET_WM_WINDOW_TYPE_").to_lowercase()
+        }
+        // Windows without a type are to be treated as normal ones
+        None => "normal".to_string(),
+    }
+}
+
 // TODO: cacke pict_format iterator
 fn has_alpha(conn: &xcb::Connection, colormap: xcb::Colormap) -> bool {
     for format in
@@ -118,3 +719,105 @@ fn has_alpha(conn: &xcb::Connection, colormap: xcb::Colormap) -> bool {
     }
     false
 }
+
+/// [x, y, width, height] of an X rectangle
+fn rect_array(rect: &xcb::Rectangle) -> [i32; 4] {
+    [
+        rect.x() as i32,
+        rect.y() as i32,
+        rect.width() as i32,
+        rect.height() as i32,
+    ]
+}
+
+#[cfg(test)]
+impl Window {
+    /// Mapped 100x100 window at the origin, made up without a server
+    pub fn for_test() -> Window {
+        Window {
+            id: 1,
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 100,
+            border_width: 0,
+            mapped: true,
+            override_redirect: false,
+            alpha: false,
+            visual: 0,
+            pixmap: 0,
+            context: (),
+            damage: 0,
+            shape: Vec::new(),
+            client: 1,
+            props: Default::default(),
+            opacity: 1.0,
+            blur_region: None,
+            client_offset: (0, 0),
+            color_filters: None,
+            fade: None,
+            anim: None,
+            destroyed: false,
+            focused: false,
+            effects: Default::default(),
+            resized: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clips_blur_region_to_shape() {
+        let mut win = Window::for_test();
+        win.border_width = 2;
+        win.client_offset = (10, 20);
+        // Two disjoint parts, like a window with a cut out
+        win.shape = vec![
+            xcb::Rectangle::new(0, 0, 104, 50),
+            xcb::Rectangle::new(0, 60, 104, 44),
+        ];
+        win.blur_region = Some(vec![xcb::Rectangle::new(0, 0, 200, 40)]);
+        let rects: Vec<_> = win
+            .blur_rects()
+            .iter()
+            .map(|r| (r.x(), r.y(), r.width(), r.height()))
+            .collect();
+        assert_eq!(rects, [(12, 22, 92, 28), (12, 60, 92, 2)]);
+    }
+
+    #[test]
+    fn blurs_whole_shape_without_region() {
+        let mut win = Window::for_test();
+        win.shape = vec![xcb::Rectangle::new(0, 0, 100, 100)];
+        win.blur_region = Some(Vec::new());
+        assert_eq!(win.blur_rects().len(), 1);
+        assert_eq!(win.blur_rects()[0].width(), 100);
+    }
+
+    #[test]
+    fn dims_unfocused_windows() {
+        let mut win = Window::for_test();
+        win.props.window_type = "normal".into();
+        assert!(win.dimmed());
+        win.focused = true;
+        assert!(!win.dimmed());
+    }
+
+    #[test]
+    fn leaves_menus_and_tooltips_alone() {
+        for window_type in ["dock", "popup_menu", "dropdown_menu", "tooltip"] {
+            let mut win = Window::for_test();
+            win.props.window_type = window_type.into();
+            assert!(!win.dimmed(), "{} is dimmed", window_type);
+        }
+        let mut win = Window::for_test();
+        win.override_redirect = true;
+        assert!(!win.dimmed());
+        // Unless a rule says otherwise
+        win.effects.dim = Some(true);
+        assert!(win.dimmed());
+    }
+}